@@ -1,3 +1,5 @@
+import { DiscountClass } from "../generated/api";
+
 // [START discount-function.delivery.run]
 export function cartDeliveryOptionsDiscountsGenerateRun(input) {
   // [START discount-function.delivery.run.body]
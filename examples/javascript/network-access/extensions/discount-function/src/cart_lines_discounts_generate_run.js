@@ -1,3 +1,5 @@
+import { DiscountClass } from "../generated/api";
+
 // [START discount-function.cart.run]
 export function cartLinesDiscountsGenerateRun(input) {
   // [START discount-function.cart.run.body]
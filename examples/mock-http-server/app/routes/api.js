@@ -106,8 +106,16 @@ const hashWithSHA256 = async (input) => {
   return hashHex;
 };
 
+// Delivery fetch targets that send their delivery groups get real targets.
+const deliveryGroupTargets = (deliveryGroups) => {
+  if (!deliveryGroups || deliveryGroups.length === 0) {
+    return [{ deliveryGroup: { id: "gid://shopify/DeliveryGroup/0" } }];
+  }
+  return deliveryGroups.map(({ id }) => ({ deliveryGroup: { id } }));
+};
+
 const handle = (body) => {
-  const { enteredDiscountCodes } = JSON.parse(body);
+  const { enteredDiscountCodes, deliveryGroups } = JSON.parse(body);
   // [START mock-http-server.results]
   const validDiscountCodes = enteredDiscountCodes.filter((code) =>
     [
@@ -117,6 +125,11 @@ const handle = (body) => {
     ].includes(code),
   );
 
+  // Unknown codes are rejected with a reason that the function writes to its logs
+  const rejectedDiscountCodes = enteredDiscountCodes.filter(
+    (code) => !validDiscountCodes.includes(code),
+  );
+
   const cartOperations = [];
   const deliveryOperations = [];
   const validationOperations = [];
@@ -191,19 +204,25 @@ const handle = (body) => {
                   value: "100",
                 },
               },
-              targets: [
-                {
-                  deliveryGroup: {
-                    id: "gid://shopify/DeliveryGroup/0",
-                  },
-                },
-              ],
+              targets: deliveryGroupTargets(deliveryGroups),
             },
           ],
         },
       });
     }
   }
+  if (rejectedDiscountCodes.length > 0) {
+    validationOperations.push({
+      enteredDiscountCodesReject: {
+        codes: rejectedDiscountCodes.map((code) => ({
+          code,
+          reason: "NOT_FOUND",
+          message: `${code} is not a valid discount code`,
+        })),
+      },
+    });
+  }
+
   return json([
     ...validationOperations,
     ...cartOperations,
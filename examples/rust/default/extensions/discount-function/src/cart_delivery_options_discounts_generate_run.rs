@@ -1,44 +1,80 @@
-use super::schema;
+use super::schema::cart_delivery_options_discounts_generate_run as query;
+use discount_core::explain::explain;
+use discount_core::money::Percentage;
+use discount_core::operations::delivery_group_percentage_discount;
+use discount_core::{schema, DiscountClasses, DiscountError, ErrorKind};
 use shopify_function::prelude::*;
 use shopify_function::Result;
 
+const TARGET: &str = "cart_delivery_options_discounts_generate_run";
+
 // [START discount-function.run.delivery]
 #[shopify_function]
-fn cart_delivery_options_discounts_generate_run(
-    input: schema::cart_delivery_options_discounts_generate_run::Input,
+pub(crate) fn cart_delivery_options_discounts_generate_run(
+    input: query::Input,
 ) -> Result<schema::CartDeliveryOptionsDiscountsGenerateRunResult> {
-    let has_shipping_discount_class = input
-        .discount()
-        .discount_classes()
-        .contains(&schema::DiscountClass::Shipping);
-    if !has_shipping_discount_class {
-        return Ok(schema::CartDeliveryOptionsDiscountsGenerateRunResult { operations: vec![] });
+    let discount_classes = DiscountClasses::new(input.discount().discount_classes());
+    explain!("discount classes: {discount_classes}");
+    if !discount_classes.shipping {
+        explain!("no SHIPPING discount class, no operations");
+        return Ok(schema::CartDeliveryOptionsDiscountsGenerateRunResult::empty());
     }
 
-    let first_delivery_group = input
-        .cart()
-        .delivery_groups()
-        .first()
-        .ok_or("No delivery groups found")?;
+    let Some(first_delivery_group) = input.cart().delivery_groups().first() else {
+        let error = DiscountError::new(TARGET, ErrorKind::NoDeliveryGroups);
+        explain!("{}", error.details());
+        return error.handle(schema::CartDeliveryOptionsDiscountsGenerateRunResult::empty());
+    };
+    explain!(
+        "delivery discount: free delivery for delivery group {:?}",
+        first_delivery_group.id()
+    );
 
     Ok(schema::CartDeliveryOptionsDiscountsGenerateRunResult {
-        operations: vec![schema::DeliveryOperation::DeliveryDiscountsAdd(
-            schema::DeliveryDiscountsAddOperation {
-                selection_strategy: schema::DeliveryDiscountSelectionStrategy::All,
-                candidates: vec![schema::DeliveryDiscountCandidate {
-                    targets: vec![schema::DeliveryDiscountCandidateTarget::DeliveryGroup(
-                        schema::DeliveryGroupTarget {
-                            id: first_delivery_group.id().clone(),
-                        },
-                    )],
-                    value: schema::DeliveryDiscountCandidateValue::Percentage(schema::Percentage {
-                        value: Decimal(100.0),
-                    }),
-                    message: Some("FREE DELIVERY".to_string()),
-                    associated_discount_code: None,
-                }],
-            },
+        operations: vec![delivery_group_percentage_discount(
+            first_delivery_group.id(),
+            Percentage::HUNDRED,
+            "FREE DELIVERY",
         )],
     })
 }
 // [END discount-function.run.delivery]
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use discount_function_devtools::fixtures::run_fixtures;
+    use discount_function_devtools::properties::{
+        check_run_invariants, delivery_groups, discount_classes,
+    };
+    use discount_function_devtools::run_to_json;
+    use proptest::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    fn runs_fixtures() {
+        run_fixtures(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/cart_delivery_options_discounts_generate_run"
+            ),
+            cart_delivery_options_discounts_generate_run,
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn satisfies_run_invariants(
+            delivery_groups in delivery_groups(),
+            discount_classes in discount_classes(),
+        ) {
+            let input = json!({
+                "cart": { "deliveryGroups": delivery_groups },
+                "discount": { "discountClasses": discount_classes }
+            });
+            let output = run_to_json(cart_delivery_options_discounts_generate_run, &input.to_string());
+
+            check_run_invariants(&input, &output)?;
+        }
+    }
+}
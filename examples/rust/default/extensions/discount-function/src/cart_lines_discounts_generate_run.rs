@@ -1,84 +1,166 @@
-use super::schema;
+use super::schema::cart_lines_discounts_generate_run as query;
+use discount_core::cart::most_expensive_line;
+use discount_core::explain::explain;
+use discount_core::money::{Money, Percentage};
+use discount_core::operations::{order_percentage_discount, product_percentage_discount};
+use discount_core::{schema, targets, DiscountClasses, DiscountError, ErrorKind};
 use shopify_function::prelude::*;
 use shopify_function::Result;
 
+const TARGET: &str = "cart_lines_discounts_generate_run";
+
 // [START discount-function.run.cart]
 #[shopify_function]
-fn cart_lines_discounts_generate_run(
-    input: schema::cart_lines_discounts_generate_run::Input,
+pub(crate) fn cart_lines_discounts_generate_run(
+    input: query::Input,
 ) -> Result<schema::CartLinesDiscountsGenerateRunResult> {
-    let max_cart_line = input
-        .cart()
-        .lines()
-        .iter()
-        .max_by(|a, b| {
-            a.cost()
-                .subtotal_amount()
-                .amount()
-                .partial_cmp(b.cost().subtotal_amount().amount())
-                .unwrap_or(std::cmp::Ordering::Equal)
-        })
-        .ok_or("No cart lines found")?;
-
-    let has_order_discount_class = input
-        .discount()
-        .discount_classes()
-        .contains(&schema::DiscountClass::Order);
-    let has_product_discount_class = input
-        .discount()
-        .discount_classes()
-        .contains(&schema::DiscountClass::Product);
-
-    if !has_order_discount_class && !has_product_discount_class {
-        return Ok(schema::CartLinesDiscountsGenerateRunResult { operations: vec![] });
+    let discount_classes = DiscountClasses::new(input.discount().discount_classes());
+    explain!("discount classes: {discount_classes}");
+
+    if !discount_classes.has_cart_lines_class() {
+        explain!("no PRODUCT or ORDER discount class, no operations");
+        return Ok(schema::CartLinesDiscountsGenerateRunResult::empty());
     }
 
+    // Amounts that aren't numbers compare below every other amount, so they
+    // can't hide the most expensive line
+    let Some(max_cart_line) = most_expensive_line(input.cart().lines(), |line| {
+        Money::from_decimal(*line.cost().subtotal_amount().amount())
+    }) else {
+        let error = DiscountError::new(TARGET, ErrorKind::NoCartLines);
+        explain!("{}", error.details());
+        return error.handle(schema::CartLinesDiscountsGenerateRunResult::empty());
+    };
+
     let mut operations = vec![];
 
     // Check if the discount has the ORDER class
-    if has_order_discount_class {
-        operations.push(schema::CartOperation::OrderDiscountsAdd(
-            schema::OrderDiscountsAddOperation {
-                selection_strategy: schema::OrderDiscountSelectionStrategy::First,
-                candidates: vec![schema::OrderDiscountCandidate {
-                    targets: vec![schema::OrderDiscountCandidateTarget::OrderSubtotal(
-                        schema::OrderSubtotalTarget {
-                            excluded_cart_line_ids: vec![],
-                        },
-                    )],
-                    message: Some("10% OFF ORDER".to_string()),
-                    value: schema::OrderDiscountCandidateValue::Percentage(schema::Percentage {
-                        value: Decimal(10.0),
-                    }),
-                    conditions: None,
-                    associated_discount_code: None,
-                }],
-            },
+    if !discount_classes.order {
+        explain!("order discount skipped: no ORDER discount class");
+    } else {
+        explain!("order discount: 10% off the order subtotal");
+        operations.push(order_percentage_discount(
+            Percentage::whole(10),
+            "10% OFF ORDER",
         ));
     }
 
     // Check if the discount has the PRODUCT class
-    if has_product_discount_class {
-        operations.push(schema::CartOperation::ProductDiscountsAdd(
-            schema::ProductDiscountsAddOperation {
-                selection_strategy: schema::ProductDiscountSelectionStrategy::First,
-                candidates: vec![schema::ProductDiscountCandidate {
-                    targets: vec![schema::ProductDiscountCandidateTarget::CartLine(
-                        schema::CartLineTarget {
-                            id: max_cart_line.id().clone(),
-                            quantity: None,
-                        },
-                    )],
-                    message: Some("20% OFF PRODUCT".to_string()),
-                    value: schema::ProductDiscountCandidateValue::Percentage(schema::Percentage {
-                        value: Decimal(20.0),
-                    }),
-                    associated_discount_code: None,
-                }],
-            },
+    if !discount_classes.product {
+        explain!("product discount skipped: no PRODUCT discount class");
+    } else {
+        explain!(
+            "product discount: 20% off the most expensive cart line {:?}",
+            max_cart_line.id()
+        );
+        operations.extend(product_percentage_discount(
+            vec![targets::cart_line(max_cart_line.id())],
+            Percentage::whole(20),
+            "20% OFF PRODUCT",
         ));
     }
 
     Ok(schema::CartLinesDiscountsGenerateRunResult { operations })
 }
 // [END discount_function.run.cart]
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use discount_core::explain;
+    use discount_function_devtools::cart_builder::CartBuilder;
+    use discount_function_devtools::fixtures::run_fixtures;
+    use discount_function_devtools::properties::{
+        cart_lines, check_run_invariants, discount_classes, targeted_ids,
+    };
+    use discount_function_devtools::run_to_json;
+    use proptest::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    fn runs_fixtures() {
+        run_fixtures(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/cart_lines_discounts_generate_run"
+            ),
+            cart_lines_discounts_generate_run,
+        );
+    }
+
+    #[test]
+    fn ignores_cart_lines_without_a_numeric_amount() {
+        let input = CartBuilder::new()
+            .line("gid://shopify/CartLine/0", 100.0)
+            .line("gid://shopify/CartLine/1", f64::NAN)
+            .discount_classes(["PRODUCT"])
+            .build();
+
+        let output = run_to_json(cart_lines_discounts_generate_run, &input);
+
+        assert_eq!(
+            targeted_ids(&output, "cartLine"),
+            vec!["gid://shopify/CartLine/0"]
+        );
+    }
+
+    #[test]
+    fn fails_with_an_error_code_without_cart_lines() {
+        let input = CartBuilder::new().discount_classes(["ORDER"]).build();
+        explain::take_trace();
+
+        let output = run_to_json(cart_lines_discounts_generate_run, &input);
+
+        assert_eq!(output, json!({ "error": "No cart lines found" }));
+        assert_eq!(
+            explain::take_trace().last().map(String::as_str),
+            Some("NO_CART_LINES in cart_lines_discounts_generate_run at cart.lines: No cart lines found")
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn satisfies_run_invariants(
+            lines in cart_lines(),
+            discount_classes in discount_classes(),
+        ) {
+            let input = json!({
+                "cart": { "lines": lines },
+                "discount": { "discountClasses": discount_classes }
+            });
+            let output = run_to_json(cart_lines_discounts_generate_run, &input.to_string());
+
+            check_run_invariants(&input, &output)?;
+        }
+
+        #[test]
+        fn discounts_the_most_expensive_cart_line(lines in cart_lines()) {
+            let input = json!({
+                "cart": { "lines": lines },
+                "discount": { "discountClasses": ["PRODUCT"] }
+            });
+            let output = run_to_json(cart_lines_discounts_generate_run, &input.to_string());
+
+            let amount = |id: &str| -> f64 {
+                let line = lines.iter().find(|line| line["id"] == id).unwrap();
+                line["cost"]["subtotalAmount"]["amount"].as_str().unwrap().parse().unwrap()
+            };
+            let max_amount = lines
+                .iter()
+                .filter_map(|line| line["id"].as_str())
+                .map(amount)
+                .filter(|amount| !amount.is_nan())
+                .fold(None, |max: Option<f64>, amount| Some(max.map_or(amount, |max| max.max(amount))));
+
+            let targeted_ids = targeted_ids(&output, "cartLine");
+            if lines.is_empty() {
+                prop_assert!(targeted_ids.is_empty());
+            } else {
+                prop_assert_eq!(targeted_ids.len(), 1);
+                if let Some(max_amount) = max_amount {
+                    prop_assert_eq!(amount(targeted_ids[0]), max_amount);
+                }
+            }
+        }
+    }
+}
@@ -1,9 +1,15 @@
-use std::process;
 pub mod cart_delivery_options_discounts_generate_run;
 pub mod cart_lines_discounts_generate_run;
+#[cfg(not(target_arch = "wasm32"))]
+use discount_function_devtools::{run_to_json, runner::Export};
 use shopify_function::typegen;
 
-#[typegen("schema.graphql")]
+// The output types come from discount-core, and the discount classes are read
+// as strings for `DiscountClasses`
+#[typegen(
+    "schema.graphql",
+    enums_as_str = ["CountryCode", "CurrencyCode", "DiscountClass", "LanguageCode"]
+)]
 pub mod schema {
     #[query("src/cart_delivery_options_discounts_generate_run.graphql")]
     pub mod cart_delivery_options_discounts_generate_run {}
@@ -12,7 +18,84 @@ pub mod schema {
     pub mod cart_lines_discounts_generate_run {}
 }
 
+#[cfg(target_arch = "wasm32")]
 fn main() {
     eprintln!("Please invoke a named export.");
-    process::exit(1);
+    std::process::exit(1);
+}
+
+/// The exports run by the native build.
+#[cfg(not(target_arch = "wasm32"))]
+const EXPORTS: &[Export] = &[
+    ("cart_lines_discounts_generate_run", |input| {
+        run_to_json(
+            cart_lines_discounts_generate_run::cart_lines_discounts_generate_run,
+            input,
+        )
+    }),
+    ("cart_delivery_options_discounts_generate_run", |input| {
+        run_to_json(cart_delivery_options_discounts_generate_run::cart_delivery_options_discounts_generate_run, input)
+    }),
+];
+
+// Natively, runs a named export with a JSON input from a file or stdin
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    discount_function_devtools::runner::main(EXPORTS, discount_core::explain::take_trace);
+}
+
+#[cfg(test)]
+mod tests {
+    use discount_function_devtools::budgets::{
+        check_instruction_budgets, check_wasm_size, large_cart,
+    };
+    use discount_function_devtools::differential::assert_matches_javascript;
+    use discount_function_devtools::replay::assert_replays;
+
+    #[test]
+    #[ignore = "builds the release Wasm module"]
+    fn stays_within_wasm_size_budget() {
+        check_wasm_size(env!("CARGO_MANIFEST_DIR"));
+    }
+
+    #[test]
+    #[ignore = "builds the release Wasm module and needs function-runner"]
+    fn stays_within_instruction_budgets() {
+        check_instruction_budgets(
+            env!("CARGO_MANIFEST_DIR"),
+            &[
+                (
+                    "cart_lines_discounts_generate_run",
+                    large_cart(250)
+                        .discount_classes(["PRODUCT", "ORDER"])
+                        .build(),
+                ),
+                (
+                    "cart_delivery_options_discounts_generate_run",
+                    large_cart(250).discount_classes(["SHIPPING"]).build(),
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    #[ignore = "needs FUNCTION_LOGS_DIR"]
+    fn replays_function_logs() {
+        let dir = std::env::var("FUNCTION_LOGS_DIR")
+            .expect("Set FUNCTION_LOGS_DIR to a directory of function run logs");
+        assert_replays(dir, super::EXPORTS);
+    }
+
+    #[test]
+    #[ignore = "needs node"]
+    fn matches_javascript_implementation() {
+        assert_matches_javascript(
+            env!("CARGO_MANIFEST_DIR"),
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../../../../javascript/default/extensions/discount-function"
+            ),
+            super::EXPORTS,
+        );
+    }
 }
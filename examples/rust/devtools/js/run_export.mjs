@@ -0,0 +1,29 @@
+// Runs an export of a JavaScript discount function extension with the JSON
+// input from stdin, and writes the JSON output to stdout. A thrown error is
+// written as {"error": "<message>"}, like the Rust examples' native builds.
+//
+// Usage: node run_export.mjs <extension directory> <export name>
+
+import { readFileSync } from "node:fs";
+import { register } from "node:module";
+import { pathToFileURL } from "node:url";
+
+register("./typescript_enums.mjs", import.meta.url);
+
+const [extensionDir, exportName] = process.argv.slice(2);
+const input = JSON.parse(readFileSync(0, "utf8"));
+const exports = await import(pathToFileURL(`${extensionDir}/src/index.js`));
+
+const run = exports[exportName];
+if (typeof run !== "function") {
+  console.error(`Unknown export ${exportName}`);
+  process.exit(2);
+}
+
+let output;
+try {
+  output = run(input);
+} catch (error) {
+  output = { error: error.message };
+}
+process.stdout.write(JSON.stringify(output));
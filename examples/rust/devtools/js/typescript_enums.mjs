@@ -0,0 +1,51 @@
+// Module hooks running the JavaScript examples with Node alone, without
+// installing their dependencies or building them.
+//
+// The examples import their generated types from `../generated/api`, a
+// TypeScript file whose only runtime values are string enums. These hooks
+// resolve imports without an extension, load JavaScript files as ES modules,
+// and load a TypeScript file as its enums, each converted to a frozen object.
+
+import { readFile } from "node:fs/promises";
+
+export async function resolve(specifier, context, nextResolve) {
+  if (specifier.startsWith(".") && !/\.[cm]?[jt]s$/.test(specifier)) {
+    for (const extension of [".js", ".ts"]) {
+      try {
+        return await nextResolve(`${specifier}${extension}`, context);
+      } catch {
+        // Try the next extension
+      }
+    }
+  }
+  return nextResolve(specifier, context);
+}
+
+export async function load(url, context, nextLoad) {
+  // The examples' package.json files don't declare a module type
+  if (url.endsWith(".js")) {
+    return nextLoad(url, { ...context, format: "module" });
+  }
+  if (!url.endsWith(".ts")) {
+    return nextLoad(url, context);
+  }
+  const source = await readFile(new URL(url), "utf8");
+  return {
+    format: "module",
+    source: enumsToJavaScript(source),
+    shortCircuit: true,
+  };
+}
+
+function enumsToJavaScript(source) {
+  const enums = [];
+  for (const [, name, body] of source.matchAll(
+    /export enum (\w+) \{([\s\S]*?)\n\}/g,
+  )) {
+    const members = [...body.matchAll(/^\s*(\w+) = ("[^"]*"),?$/gm)].map(
+      ([, key, value]) => `${key}: ${value}`,
+    );
+    enums.push(`export const ${name} = Object.freeze({ ${members.join(", ")} });`);
+  }
+  return enums.join("\n");
+}
@@ -0,0 +1,220 @@
+//! Instruction-count and Wasm size budgets.
+//!
+//! Shopify rejects a function whose Wasm module is too large, and fails a run
+//! that executes too many instructions. The checks build the release module the
+//! same way `shopify app build` does, then measure its size, and run exports
+//! with Shopify's [function-runner](https://github.com/Shopify/function-runner)
+//! against large carts. They fail when the module or an export goes over the
+//! budgets in the crate's `tests/budgets.json`:
+//!
+//! ```json
+//! {
+//!   "wasmSizeBytes": 110000,
+//!   "instructions": {
+//!     "cart_lines_discounts_generate_run": 450000
+//!   }
+//! }
+//! ```
+//!
+//! The budgets sit a little above the measurements, so a change that makes
+//! the function noticeably larger or slower fails the checks long before
+//! Shopify's limits. `UPDATE_BUDGETS=1` rewrites them from the measurements,
+//! with [`HEADROOM_PERCENT`] of headroom, once such a change is accepted.
+
+use crate::cart_builder::CartBuilder;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The file holding the budgets, relative to the crate.
+pub const BUDGETS_PATH: &str = "tests/budgets.json";
+/// The Wasm module built for Shopify, relative to the crate.
+pub const WASM_PATH: &str = "target/wasm32-wasip1/release/discount-function-rs.wasm";
+/// Environment variable overriding the path of the function-runner binary.
+pub const FUNCTION_RUNNER_ENV: &str = "FUNCTION_RUNNER";
+/// Environment variable rewriting the budgets from the measurements when set
+/// to `1`.
+pub const UPDATE_BUDGETS_ENV: &str = "UPDATE_BUDGETS";
+/// The headroom of the budgets written with `UPDATE_BUDGETS=1`, in percent of
+/// the measurements.
+pub const HEADROOM_PERCENT: u64 = 10;
+/// Shopify's limit on the size of a function's Wasm module.
+pub const MAX_WASM_SIZE_BYTES: u64 = 256 * 1024;
+/// Shopify's limit on the number of instructions of a run.
+pub const MAX_INSTRUCTIONS: u64 = 11_000_000;
+
+/// A cart with `line_count` lines, every other one in the discount's
+/// collections, and three delivery groups with three delivery options each.
+pub fn large_cart(line_count: usize) -> CartBuilder {
+    let mut cart = CartBuilder::new();
+    for index in 0..line_count {
+        cart = cart.line(
+            &format!("gid://shopify/CartLine/{index}"),
+            10.0 + index as f64,
+        );
+        if index % 2 == 0 {
+            cart = cart.with_product_in_collection();
+        }
+    }
+    for index in 0..3 {
+        cart = cart
+            .delivery_group(&format!("gid://shopify/CartDeliveryGroup/{index}"))
+            .delivery_option("standard", 5.0)
+            .delivery_option("express", 15.0)
+            .delivery_option("overnight", 30.0);
+    }
+    cart
+}
+
+/// Builds the release Wasm module of the crate at `manifest_dir` and checks
+/// its size against the crate's budget.
+pub fn check_wasm_size(manifest_dir: impl AsRef<Path>) {
+    let manifest_dir = manifest_dir.as_ref();
+    let mut budgets = read_budgets(manifest_dir);
+    let wasm_path = build_wasm(manifest_dir);
+
+    let wasm_size = fs::metadata(&wasm_path)
+        .unwrap_or_else(|error| panic!("Failed to read {}: {error}", wasm_path.display()))
+        .len();
+    if update_budgets() {
+        budgets["wasmSizeBytes"] = with_headroom(wasm_size, MAX_WASM_SIZE_BYTES).into();
+        write_budgets(manifest_dir, &budgets);
+    }
+    let budget = budgets["wasmSizeBytes"]
+        .as_u64()
+        .expect("Missing wasmSizeBytes budget");
+    println!("wasm size: {wasm_size} bytes (budget {budget})");
+    assert!(
+        wasm_size <= budget,
+        "wasm size: {wasm_size} bytes is over the budget of {budget}"
+    );
+}
+
+/// Builds the release Wasm module of the crate at `manifest_dir`, runs each
+/// `(export, input)` pair with function-runner, and checks the number of
+/// instructions against the crate's budgets. Every export runs before failing,
+/// and the instruction counts are printed whether or not they pass.
+pub fn check_instruction_budgets(manifest_dir: impl AsRef<Path>, runs: &[(&str, String)]) {
+    let manifest_dir = manifest_dir.as_ref();
+    let mut budgets = read_budgets(manifest_dir);
+    let wasm_path = build_wasm(manifest_dir);
+    let mut failures = vec![];
+
+    for (export, input) in runs {
+        let instructions = count_instructions(&wasm_path, export, input);
+        if update_budgets() {
+            budgets["instructions"][export] = with_headroom(instructions, MAX_INSTRUCTIONS).into();
+        }
+        let budget = budgets["instructions"][export]
+            .as_u64()
+            .unwrap_or_else(|| panic!("Missing instructions budget for {export}"));
+        println!("{export}: {instructions} instructions (budget {budget})");
+        if instructions > budget {
+            failures.push(format!(
+                "{export}: {instructions} instructions is over the budget of {budget}"
+            ));
+        }
+    }
+
+    if update_budgets() {
+        write_budgets(manifest_dir, &budgets);
+    }
+    assert!(
+        failures.is_empty(),
+        "{} budget(s) exceeded:\n    {}\n",
+        failures.len(),
+        failures.join("\n    ")
+    );
+}
+
+fn read_budgets(manifest_dir: &Path) -> serde_json::Value {
+    let path = manifest_dir.join(BUDGETS_PATH);
+    let budgets = fs::read_to_string(&path)
+        .unwrap_or_else(|error| panic!("Failed to read {}: {error}", path.display()));
+    serde_json::from_str(&budgets)
+        .unwrap_or_else(|error| panic!("Invalid JSON in {}: {error}", path.display()))
+}
+
+fn write_budgets(manifest_dir: &Path, budgets: &serde_json::Value) {
+    let path = manifest_dir.join(BUDGETS_PATH);
+    let budgets = serde_json::to_string_pretty(budgets).expect("Failed to serialize the budgets");
+    fs::write(&path, budgets + "\n")
+        .unwrap_or_else(|error| panic!("Failed to write {}: {error}", path.display()));
+}
+
+fn update_budgets() -> bool {
+    env::var(UPDATE_BUDGETS_ENV).is_ok_and(|value| value == "1")
+}
+
+/// The budget of a measurement: [`HEADROOM_PERCENT`] above it, rounded up to
+/// a thousand, and never above Shopify's `limit`.
+fn with_headroom(measurement: u64, limit: u64) -> u64 {
+    let budget = measurement + (measurement * HEADROOM_PERCENT).div_ceil(100);
+    (budget.div_ceil(1000) * 1000).min(limit)
+}
+
+fn build_wasm(manifest_dir: &Path) -> PathBuf {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let status = Command::new(cargo)
+        .args(["build", "--target=wasm32-wasip1", "--release"])
+        .current_dir(manifest_dir)
+        .status()
+        .expect("Failed to run cargo");
+    assert!(status.success(), "Failed to build the Wasm module");
+    manifest_dir.join(WASM_PATH)
+}
+
+/// Runs `export` with function-runner and returns the number of instructions
+/// it executed.
+fn count_instructions(wasm_path: &Path, export: &str, input: &str) -> u64 {
+    let function_runner =
+        env::var(FUNCTION_RUNNER_ENV).unwrap_or_else(|_| "function-runner".to_string());
+    let mut child = Command::new(&function_runner)
+        .arg("--function")
+        .arg(wasm_path)
+        .args(["--export", export, "--json"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap_or_else(|error| {
+            panic!("Failed to run {function_runner}: {error}. Download it from https://github.com/Shopify/function-runner/releases, or set {FUNCTION_RUNNER_ENV} to its path.")
+        });
+    child
+        .stdin
+        .take()
+        .expect("Failed to open stdin")
+        .write_all(input.as_bytes())
+        .expect("Failed to write input");
+    let output = child
+        .wait_with_output()
+        .expect("Failed to run function-runner");
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap_or_else(|_| {
+        panic!(
+            "{export} failed:\n{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        )
+    });
+    assert!(output.status.success(), "{export} failed: {report}");
+    report["instructions"]
+        .as_u64()
+        .unwrap_or_else(|| panic!("No instruction count in the report of {export}: {report}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_headroom_up_to_the_limit() {
+        assert_eq!(with_headroom(96_995, MAX_WASM_SIZE_BYTES), 107_000);
+        assert_eq!(
+            with_headroom(250_000, MAX_WASM_SIZE_BYTES),
+            MAX_WASM_SIZE_BYTES
+        );
+    }
+}
@@ -0,0 +1,410 @@
+//! A builder for the input JSON of the function targets.
+//!
+//! The builder writes every field that any target of the examples selects,
+//! and each target ignores the fields its input query doesn't select. Tests
+//! describe the cart instead of the exact shape of a query, so they keep
+//! working when a query selects more or fewer fields.
+//!
+//! ```
+//! use discount_function_devtools::cart_builder::CartBuilder;
+//! use serde_json::json;
+//!
+//! let input = CartBuilder::new()
+//!     .line("gid://shopify/CartLine/0", 40.0)
+//!     .with_product_in_collection()
+//!     .line("gid://shopify/CartLine/1", 10.0)
+//!     .discount_classes(["PRODUCT", "ORDER"])
+//!     .metafield(json!({ "cartLinePercentage": 20 }))
+//!     .build();
+//! ```
+
+use serde_json::{json, Value};
+use shopify_function::prelude::Decimal;
+
+#[derive(Default)]
+pub struct CartBuilder {
+    lines: Vec<Line>,
+    delivery_groups: Vec<DeliveryGroup>,
+    discount_classes: Vec<String>,
+    metafield: Option<Value>,
+    fetch_result: Option<Value>,
+    entered_discount_codes: Vec<String>,
+    buyer_identity: Option<Value>,
+    customer_tags: Option<Vec<String>>,
+    attribute: Option<String>,
+    date: Option<String>,
+    currency_code: Option<String>,
+}
+
+struct Line {
+    id: String,
+    amount: f64,
+    quantity: i32,
+    in_any_collection: bool,
+    collection_ids: Vec<String>,
+    tags: Vec<String>,
+}
+
+struct DeliveryGroup {
+    id: String,
+    delivery_options: Vec<DeliveryOption>,
+}
+
+struct DeliveryOption {
+    handle: String,
+    amount: f64,
+}
+
+impl CartBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a cart line for a product variant, with a quantity of 1 and a
+    /// product that isn't in any of the discount's collections.
+    pub fn line(mut self, id: &str, amount: f64) -> Self {
+        self.lines.push(Line {
+            id: id.to_string(),
+            amount,
+            quantity: 1,
+            in_any_collection: false,
+            collection_ids: vec![],
+            tags: vec![],
+        });
+        self
+    }
+
+    /// Sets the quantity of the last line.
+    pub fn quantity(mut self, quantity: i32) -> Self {
+        self.last_line().quantity = quantity;
+        self
+    }
+
+    /// Puts the product of the last line in all of the discount's collections,
+    /// so that `inAnyCollection(ids: $collectionIds)` is true.
+    pub fn with_product_in_collection(mut self) -> Self {
+        self.last_line().in_any_collection = true;
+        self
+    }
+
+    /// Puts the product of the last line in the collection `id`.
+    pub fn in_collection(mut self, id: &str) -> Self {
+        self.last_line().collection_ids.push(id.to_string());
+        self
+    }
+
+    /// Adds a tag to the product of the last line.
+    pub fn product_tag(mut self, tag: &str) -> Self {
+        self.last_line().tags.push(tag.to_string());
+        self
+    }
+
+    /// Adds a delivery group without delivery options.
+    pub fn delivery_group(mut self, id: &str) -> Self {
+        self.delivery_groups.push(DeliveryGroup {
+            id: id.to_string(),
+            delivery_options: vec![],
+        });
+        self
+    }
+
+    /// Adds a delivery option to the last delivery group. The first option of a
+    /// group is the selected one.
+    pub fn delivery_option(mut self, handle: &str, amount: f64) -> Self {
+        self.delivery_groups
+            .last_mut()
+            .expect("delivery_option must follow a delivery_group")
+            .delivery_options
+            .push(DeliveryOption {
+                handle: handle.to_string(),
+                amount,
+            });
+        self
+    }
+
+    pub fn discount_classes<'a>(
+        mut self,
+        discount_classes: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        self.discount_classes = discount_classes.into_iter().map(str::to_string).collect();
+        self
+    }
+
+    /// Sets the `jsonValue` of the discount's configuration metafield.
+    pub fn metafield(mut self, configuration: Value) -> Self {
+        self.metafield = Some(configuration);
+        self
+    }
+
+    /// Sets the response of the fetch target's request, for the run targets.
+    pub fn fetch_result(mut self, status: i32, json_body: Value) -> Self {
+        self.fetch_result = Some(json!({
+            "status": status,
+            "jsonBody": json_body
+        }));
+        self
+    }
+
+    pub fn entered_discount_codes<'a>(mut self, codes: impl IntoIterator<Item = &'a str>) -> Self {
+        self.entered_discount_codes = codes.into_iter().map(str::to_string).collect();
+        self
+    }
+
+    pub fn buyer(mut self, email: &str, is_authenticated: bool) -> Self {
+        self.buyer_identity = Some(json!({
+            "email": email,
+            "isAuthenticated": is_authenticated
+        }));
+        self
+    }
+
+    /// Makes the buyer a customer with `tags`.
+    pub fn customer_tags<'a>(mut self, tags: impl IntoIterator<Item = &'a str>) -> Self {
+        self.customer_tags = Some(tags.into_iter().map(str::to_string).collect());
+        self
+    }
+
+    /// Sets the date in the shop's time zone, as `YYYY-MM-DD`.
+    pub fn date(mut self, date: &str) -> Self {
+        self.date = Some(date.to_string());
+        self
+    }
+
+    /// Sets the currency of every amount in the cart.
+    pub fn currency_code(mut self, currency_code: &str) -> Self {
+        self.currency_code = Some(currency_code.to_string());
+        self
+    }
+
+    /// Sets the value of the cart attribute selected by the input query.
+    pub fn attribute(mut self, value: &str) -> Self {
+        self.attribute = Some(value.to_string());
+        self
+    }
+
+    /// The input as JSON. Fields with arguments, like `inCollections(ids:
+    /// $collectionIds)`, answer for the query variables of the same name in the
+    /// metafield.
+    pub fn to_json(&self) -> Value {
+        let subtotal: f64 = self.lines.iter().map(|line| line.amount).sum();
+        let variables = Variables::new(self.metafield.as_ref());
+        let currency_code = self
+            .currency_code
+            .as_deref()
+            .unwrap_or(DEFAULT_CURRENCY_CODE);
+        let mut buyer_identity = self.buyer_identity.clone();
+        if let Some(customer_tags) = &self.customer_tags {
+            buyer_identity.get_or_insert_with(|| json!({ "isAuthenticated": true }))["customer"] = json!({
+                "hasTags": has_tags(&variables.customer_tags, customer_tags)
+            });
+        }
+
+        json!({
+            "enteredDiscountCodes": self.entered_discount_codes,
+            "cart": {
+                "attribute": self.attribute.as_ref().map(|value| json!({ "value": value })),
+                "lines": self
+                    .lines
+                    .iter()
+                    .map(|line| line.to_json(&variables, currency_code))
+                    .collect::<Vec<_>>(),
+                "buyerIdentity": buyer_identity,
+                "cost": {
+                    "subtotalAmount": money(subtotal, currency_code)
+                },
+                "deliveryGroups": self
+                    .delivery_groups
+                    .iter()
+                    .map(|delivery_group| delivery_group.to_json(currency_code))
+                    .collect::<Vec<_>>()
+            },
+            "shop": {
+                "localTime": {
+                    "date": self.date.as_deref().unwrap_or(DEFAULT_DATE)
+                }
+            },
+            "discount": {
+                "discountClasses": self.discount_classes,
+                "metafield": self.metafield.as_ref().map(|configuration| json!({ "jsonValue": configuration }))
+            },
+            "fetchResult": self.fetch_result
+        })
+    }
+
+    /// The input as the JSON string passed to a target.
+    pub fn build(&self) -> String {
+        self.to_json().to_string()
+    }
+
+    fn last_line(&mut self) -> &mut Line {
+        self.lines
+            .last_mut()
+            .expect("Line modifiers must follow a line")
+    }
+}
+
+/// The currency of the cart when none is set.
+pub const DEFAULT_CURRENCY_CODE: &str = "USD";
+
+/// The date in the shop's time zone when none is set.
+pub const DEFAULT_DATE: &str = "2025-06-01";
+
+/// The query variables read from the discount's metafield.
+struct Variables {
+    collection_ids: Vec<String>,
+    product_tags: Vec<String>,
+    customer_tags: Vec<String>,
+}
+
+impl Variables {
+    fn new(metafield: Option<&Value>) -> Self {
+        let strings = |name: &str| -> Vec<String> {
+            metafield
+                .and_then(|metafield| metafield[name].as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|value| value.as_str().map(str::to_string))
+                .collect()
+        };
+        Self {
+            collection_ids: strings("collectionIds"),
+            product_tags: strings("productTags"),
+            customer_tags: strings("customerTags"),
+        }
+    }
+}
+
+impl Line {
+    fn to_json(&self, variables: &Variables, currency_code: &str) -> Value {
+        let in_collections: Vec<Value> = variables
+            .collection_ids
+            .iter()
+            .map(|id| {
+                json!({
+                    "collectionId": id,
+                    "isMember": self.in_any_collection || self.collection_ids.contains(id)
+                })
+            })
+            .collect();
+        json!({
+            "id": self.id,
+            "quantity": self.quantity,
+            "cost": {
+                "subtotalAmount": money(self.amount, currency_code)
+            },
+            "merchandise": {
+                "__typename": "ProductVariant",
+                "product": {
+                    "inAnyCollection": self.in_any_collection
+                        || in_collections.iter().any(|membership| membership["isMember"] == true),
+                    "inCollections": in_collections,
+                    "hasTags": has_tags(&variables.product_tags, &self.tags)
+                }
+            }
+        })
+    }
+}
+
+impl DeliveryGroup {
+    fn to_json(&self, currency_code: &str) -> Value {
+        json!({
+            "id": self.id,
+            "deliveryAddress": null,
+            "deliveryOptions": self
+                .delivery_options
+                .iter()
+                .map(|delivery_option| json!({
+                    "handle": delivery_option.handle,
+                    "title": delivery_option.handle,
+                    "deliveryMethodType": "SHIPPING",
+                    "cost": money(delivery_option.amount, currency_code)
+                }))
+                .collect::<Vec<_>>(),
+            "selectedDeliveryOption": self
+                .delivery_options
+                .first()
+                .map(|delivery_option| json!({
+                    "handle": delivery_option.handle,
+                    "cost": money(delivery_option.amount, currency_code)
+                }))
+        })
+    }
+}
+
+/// The `hasTags(tags:)` responses for the queried `tags`.
+fn has_tags(tags: &[String], actual_tags: &[String]) -> Vec<Value> {
+    tags.iter()
+        .map(|tag| json!({ "tag": tag, "hasTag": actual_tags.contains(tag) }))
+        .collect()
+}
+
+fn money(amount: f64, currency_code: &str) -> Value {
+    json!({
+        "amount": Decimal(amount).to_string(),
+        "currencyCode": currency_code
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_cart_lines_and_discount() {
+        let input = CartBuilder::new()
+            .line("gid://shopify/CartLine/0", 40.0)
+            .with_product_in_collection()
+            .line("gid://shopify/CartLine/1", 10.0)
+            .quantity(2)
+            .discount_classes(["PRODUCT"])
+            .metafield(json!({ "cartLinePercentage": 20 }))
+            .to_json();
+
+        assert_eq!(input["cart"]["cost"]["subtotalAmount"]["amount"], "50.0");
+        assert_eq!(
+            input["cart"]["lines"][0]["merchandise"]["product"]["inAnyCollection"],
+            true
+        );
+        assert_eq!(input["cart"]["lines"][1]["quantity"], 2);
+        assert_eq!(input["discount"]["discountClasses"], json!(["PRODUCT"]));
+        assert_eq!(
+            input["discount"]["metafield"]["jsonValue"]["cartLinePercentage"],
+            20
+        );
+        assert_eq!(input["fetchResult"], Value::Null);
+    }
+
+    #[test]
+    fn answers_fields_with_arguments_for_the_metafield_variables() {
+        let input = CartBuilder::new()
+            .line("gid://shopify/CartLine/0", 40.0)
+            .in_collection("gid://shopify/Collection/2")
+            .product_tag("sale")
+            .customer_tags(["vip"])
+            .metafield(json!({
+                "collectionIds": ["gid://shopify/Collection/1", "gid://shopify/Collection/2"],
+                "productTags": ["sale", "new"],
+                "customerTags": ["vip"]
+            }))
+            .to_json();
+
+        let product = &input["cart"]["lines"][0]["merchandise"]["product"];
+        assert_eq!(
+            product["inCollections"],
+            json!([
+                { "collectionId": "gid://shopify/Collection/1", "isMember": false },
+                { "collectionId": "gid://shopify/Collection/2", "isMember": true }
+            ])
+        );
+        assert_eq!(product["inAnyCollection"], true);
+        assert_eq!(
+            product["hasTags"],
+            json!([{ "tag": "sale", "hasTag": true }, { "tag": "new", "hasTag": false }])
+        );
+        assert_eq!(
+            input["cart"]["buyerIdentity"]["customer"]["hasTags"],
+            json!([{ "tag": "vip", "hasTag": true }])
+        );
+        assert_eq!(input["shop"]["localTime"]["date"], DEFAULT_DATE);
+    }
+}
@@ -0,0 +1,207 @@
+//! Differential tests between the Rust and JavaScript implementations of an
+//! example.
+//!
+//! Every fixture input of an export runs through the Rust export and through
+//! the JavaScript function of the same name in `examples/javascript`, and the
+//! two outputs must be identical. The JavaScript functions run with Node alone,
+//! using the module hooks in `js/`, so their dependencies don't need to be
+//! installed.
+//!
+//! The JavaScript examples read the discount's metafield as a `value` string,
+//! while the Rust examples read its `jsonValue`, so the JavaScript functions get
+//! both, like Shopify gives a query selecting both.
+//!
+//! Fixtures where the implementations are known to differ are listed, with the
+//! reason, in the crate's `tests/javascript_divergences.json`:
+//!
+//! ```json
+//! {
+//!   "cart_lines_discounts_generate_run/no_cart_lines": "JavaScript fails before checking the discount classes"
+//! }
+//! ```
+//!
+//! A listed fixture whose outputs become identical fails too, so the list only
+//! holds differences that still exist.
+
+use crate::fixtures::discover;
+use crate::json_diff;
+use crate::runner::Export;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// The file listing the fixtures where the implementations differ, relative to
+/// the crate.
+pub const DIVERGENCES_PATH: &str = "tests/javascript_divergences.json";
+/// Environment variable overriding the path of the Node binary.
+pub const NODE_ENV: &str = "NODE";
+
+const RUN_EXPORT_SCRIPT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/js/run_export.mjs");
+
+/// Runs the fixtures of every export of the crate at `manifest_dir` through
+/// the Rust export and the JavaScript extension at `javascript_dir`, and fails
+/// when their outputs differ on a fixture that isn't a known divergence.
+pub fn assert_matches_javascript(
+    manifest_dir: impl AsRef<Path>,
+    javascript_dir: impl AsRef<Path>,
+    exports: &[Export],
+) {
+    let manifest_dir = manifest_dir.as_ref();
+    let javascript_dir = javascript_dir.as_ref();
+    let divergences = read_divergences(manifest_dir);
+    let mut failures = vec![];
+
+    for (export, run_export) in exports {
+        for fixture in discover(manifest_dir.join("tests/fixtures").join(export)) {
+            let name = format!("{export}/{}", fixture.name);
+            let input = fixture.read_input();
+            let rust = without_nulls(run_export(&input));
+            let javascript = run_javascript(
+                javascript_dir,
+                &javascript_export(export),
+                &with_metafield_value(&input),
+            )
+            .unwrap_or_else(|error| panic!("{name}: {error}"));
+
+            let differences = json_diff::diff(&javascript, &rust);
+            match (differences.is_empty(), divergences.get(&name)) {
+                (true, Some(_)) => failures.push(format!(
+                    "{name}: matches JavaScript now, remove it from {DIVERGENCES_PATH}"
+                )),
+                (false, None) => failures.push(format!(
+                    "{name}: differs from JavaScript\n    {}",
+                    differences.join("\n    ")
+                )),
+                (false, Some(reason)) => println!("{name}: known divergence, {reason}"),
+                (true, None) => {}
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} fixture(s) failed:\n\n{}\n\nThe differences are from the JavaScript output to the Rust output.\n",
+        failures.len(),
+        failures.join("\n\n")
+    );
+}
+
+/// Runs `export` of the JavaScript extension at `javascript_dir` with Node.
+pub fn run_javascript(javascript_dir: &Path, export: &str, input: &str) -> Result<Value, String> {
+    let node = env::var(NODE_ENV).unwrap_or_else(|_| "node".to_string());
+    let mut child = Command::new(&node)
+        .arg(RUN_EXPORT_SCRIPT)
+        .arg(javascript_dir)
+        .arg(export)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| {
+            format!("Failed to run {node}: {error}. Install Node, or set {NODE_ENV} to its path.")
+        })?;
+    child
+        .stdin
+        .take()
+        .expect("Failed to open stdin")
+        .write_all(input.as_bytes())
+        .map_err(|error| format!("Failed to write input: {error}"))?;
+    let output = child
+        .wait_with_output()
+        .map_err(|error| format!("Failed to run {node}: {error}"))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "{export} failed in JavaScript:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    let output = serde_json::from_slice(&output.stdout)
+        .map_err(|error| format!("Invalid JSON output from {export}: {error}"))?;
+    Ok(without_nulls(output))
+}
+
+/// The name of the JavaScript function implementing a Rust export, such as
+/// `cartLinesDiscountsGenerateRun` for `cart_lines_discounts_generate_run`.
+pub fn javascript_export(export: &str) -> String {
+    let mut words = export.split('_');
+    let first = words.next().unwrap_or_default().to_string();
+    words.fold(first, |mut name, word| {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            name.extend(first.to_uppercase());
+            name.push_str(chars.as_str());
+        }
+        name
+    })
+}
+
+/// Adds the `value` string of the discount's metafield next to its `jsonValue`.
+fn with_metafield_value(input: &str) -> String {
+    let mut input: Value = serde_json::from_str(input).expect("Invalid JSON input");
+    if let Some(metafield) = input
+        .pointer_mut("/discount/metafield")
+        .and_then(Value::as_object_mut)
+    {
+        if let Some(json_value) = metafield.get("jsonValue") {
+            let value = json_value.to_string();
+            metafield.entry("value").or_insert(Value::String(value));
+        }
+    }
+    input.to_string()
+}
+
+/// Removes the object fields set to null. The Rust examples write optional
+/// fields as null, while the JavaScript examples leave them out.
+fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(object) => Value::Object(
+            object
+                .into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key, without_nulls(value)))
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(without_nulls).collect()),
+        value => value,
+    }
+}
+
+fn read_divergences(manifest_dir: &Path) -> BTreeMap<String, String> {
+    let path = manifest_dir.join(DIVERGENCES_PATH);
+    let Ok(divergences) = fs::read_to_string(&path) else {
+        return BTreeMap::new();
+    };
+    serde_json::from_str(&divergences)
+        .unwrap_or_else(|error| panic!("Invalid JSON in {}: {error}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn names_javascript_exports_in_camel_case() {
+        assert_eq!(
+            javascript_export("cart_lines_discounts_generate_run"),
+            "cartLinesDiscountsGenerateRun"
+        );
+    }
+
+    #[test]
+    fn removes_null_fields() {
+        let output = json!({
+            "operations": [{ "message": null, "targets": [{ "quantity": null, "id": "1" }] }]
+        });
+
+        assert_eq!(
+            without_nulls(output),
+            json!({ "operations": [{ "targets": [{ "id": "1" }] }] })
+        );
+    }
+}
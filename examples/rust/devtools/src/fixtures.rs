@@ -0,0 +1,95 @@
+//! File-based fixtures for function targets.
+//!
+//! Each target has a directory, conventionally `tests/fixtures/<target>/`,
+//! holding pairs of `<name>.input.json` and `<name>.expected.json` files. The
+//! input is the JSON Shopify sends to the target, and the expected file is the
+//! JSON output of the target, or `{"error": "<message>"}` when the target is
+//! expected to fail.
+//!
+//! Expected files are snapshots: a missing or outdated expected file fails the
+//! fixture, writes the actual output to `<name>.expected.json.new` for review,
+//! and is accepted by running the tests with `UPDATE_SNAPSHOTS=1`.
+
+use crate::{run_to_json, snapshots};
+use shopify_function::wasm_api::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const INPUT_SUFFIX: &str = ".input.json";
+const EXPECTED_SUFFIX: &str = ".expected.json";
+
+/// A single input file found in a fixture directory.
+pub struct Fixture {
+    pub name: String,
+    pub input_path: PathBuf,
+}
+
+impl Fixture {
+    /// The file holding the expected output, next to the input file.
+    pub fn expected_path(&self) -> PathBuf {
+        self.input_path
+            .with_file_name(format!("{}{EXPECTED_SUFFIX}", self.name))
+    }
+
+    pub fn read_input(&self) -> String {
+        read(&self.input_path)
+    }
+}
+
+/// Returns the fixtures in `dir`, sorted by name.
+///
+/// Panics if the directory doesn't exist or contains no fixtures, so that a
+/// misspelled target name doesn't silently pass.
+pub fn discover(dir: impl AsRef<Path>) -> Vec<Fixture> {
+    let dir = dir.as_ref();
+    let entries = fs::read_dir(dir)
+        .unwrap_or_else(|error| panic!("Failed to read fixtures in {}: {error}", dir.display()));
+
+    let mut fixtures: Vec<Fixture> = entries
+        .filter_map(|entry| {
+            let input_path = entry.ok()?.path();
+            let file_name = input_path.file_name()?.to_str()?;
+            let name = file_name.strip_suffix(INPUT_SUFFIX)?.to_string();
+            Some(Fixture { name, input_path })
+        })
+        .collect();
+    fixtures.sort_by(|a, b| a.name.cmp(&b.name));
+
+    assert!(
+        !fixtures.is_empty(),
+        "No *{INPUT_SUFFIX} fixtures found in {}",
+        dir.display()
+    );
+    fixtures
+}
+
+/// Runs every fixture in `dir` through `function` and compares the output with
+/// the expected output. All fixtures run before failing, and the failure
+/// message lists the differences for each failing fixture.
+pub fn run_fixtures<F, P, O>(dir: impl AsRef<Path>, function: F)
+where
+    F: Fn(P) -> shopify_function::Result<O>,
+    P: Deserialize,
+    O: Serialize,
+{
+    let mut failures = vec![];
+
+    for fixture in discover(dir) {
+        let actual = run_to_json(&function, &fixture.read_input());
+        if let Err(failure) = snapshots::check_snapshot(&fixture.expected_path(), &actual) {
+            failures.push(format!("{}:\n    {failure}", fixture.name));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} fixture(s) failed:\n\n{}\n",
+        failures.len(),
+        failures.join("\n\n")
+    );
+}
+
+fn read(path: &Path) -> String {
+    fs::read_to_string(path)
+        .unwrap_or_else(|error| panic!("Failed to read {}: {error}", path.display()))
+}
@@ -0,0 +1,114 @@
+//! Strategies generating malformed and adversarial JSON, for fuzzing the
+//! parts of an input that come from outside of Shopify, like the body of a
+//! fetch response.
+//!
+//! The fuzz tests run with proptest, so the number of cases can be raised for a
+//! longer session with the `PROPTEST_CASES` environment variable.
+
+use proptest::prelude::*;
+use serde_json::{Map, Value};
+
+/// Arbitrary JSON. Object keys are mostly picked from `keys`, so that the
+/// generated values reach past the first field a deserializer looks at.
+pub fn arbitrary_json(keys: &'static [&'static str]) -> impl Strategy<Value = Value> {
+    let leaf = prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::Bool),
+        any::<i64>().prop_map(Value::from),
+        any::<f64>().prop_map(Value::from),
+        adversarial_string().prop_map(Value::String),
+    ];
+    leaf.prop_recursive(6, 64, 8, move |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..8).prop_map(Value::Array),
+            prop::collection::vec((key(keys), inner), 0..8)
+                .prop_map(|entries| Value::Object(entries.into_iter().collect())),
+        ]
+    })
+}
+
+/// Strings that are likely to break parsing: numbers out of range, `NaN`,
+/// enum values with the wrong case, and long or unusual text.
+pub fn adversarial_string() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just(String::new()),
+        Just("NaN".to_string()),
+        Just("-inf".to_string()),
+        Just("1e400".to_string()),
+        Just("-0.0".to_string()),
+        Just("ALL".to_string()),
+        Just("first".to_string()),
+        any::<f64>().prop_map(|number| number.to_string()),
+        ".{0,32}",
+        "[a-z]{1,8}".prop_map(|text| text.repeat(1_000)),
+    ]
+}
+
+/// Copies of `seeds` with some of their values replaced by arbitrary JSON,
+/// and some of their object fields removed.
+pub fn corrupted(seeds: Vec<Value>, keys: &'static [&'static str]) -> impl Strategy<Value = Value> {
+    (
+        prop::sample::select(seeds),
+        prop::collection::vec((any::<prop::sample::Index>(), arbitrary_json(keys)), 1..4),
+        prop::collection::vec(any::<prop::sample::Index>(), 0..3),
+    )
+        .prop_map(|(mut value, replacements, removals)| {
+            for (index, replacement) in replacements {
+                let count = count_nodes(&value);
+                *nth_node(&mut value, index.index(count)) = replacement;
+            }
+            for index in removals {
+                let count = count_nodes(&value);
+                if let Value::Object(object) = nth_node(&mut value, index.index(count)) {
+                    if let Some(key) = object.keys().next().cloned() {
+                        object.remove(&key);
+                    }
+                }
+            }
+            value
+        })
+}
+
+fn key(keys: &'static [&'static str]) -> impl Strategy<Value = String> {
+    prop_oneof![
+        4 => prop::sample::select(keys).prop_map(str::to_string),
+        1 => "[a-zA-Z]{1,12}",
+    ]
+}
+
+fn count_nodes(value: &Value) -> usize {
+    1 + match value {
+        Value::Array(values) => values.iter().map(count_nodes).sum(),
+        Value::Object(object) => object.values().map(count_nodes).sum(),
+        _ => 0,
+    }
+}
+
+/// The node at position `n` of a depth-first walk of `value`.
+fn nth_node(value: &mut Value, n: usize) -> &mut Value {
+    if n == 0 {
+        return value;
+    }
+    let mut n = n - 1;
+    let children: Vec<&mut Value> = match value {
+        Value::Array(values) => values.iter_mut().collect(),
+        Value::Object(object) => object.values_mut().collect(),
+        _ => unreachable!("Scalars have no children"),
+    };
+    for child in children {
+        let count = count_nodes(child);
+        if n < count {
+            return nth_node(child, n);
+        }
+        n -= count;
+    }
+    unreachable!("Node {n} is out of range")
+}
+
+/// An object nested `depth` levels deep, within the nesting limit of the JSON
+/// parser used natively.
+pub fn deeply_nested(depth: usize) -> Value {
+    (0..depth).fold(Value::Null, |value, _| {
+        Value::Object(Map::from_iter([("value".to_string(), value)]))
+    })
+}
@@ -0,0 +1,101 @@
+//! Readable differences between two JSON values.
+
+use serde_json::Value;
+
+/// Returns one line per difference between `expected` and `actual`, each
+/// prefixed with the JSON path where it occurs. Strings holding the same number,
+/// such as `"10"` and `"10.0"`, are considered equal because Shopify's
+/// `Decimal` scalar can be written either way.
+pub fn diff(expected: &Value, actual: &Value) -> Vec<String> {
+    let mut differences = vec![];
+    diff_at("$", expected, actual, &mut differences);
+    differences
+}
+
+fn diff_at(path: &str, expected: &Value, actual: &Value, differences: &mut Vec<String>) {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, expected_value) in expected {
+                let path = format!("{path}.{key}");
+                match actual.get(key) {
+                    Some(actual_value) => diff_at(&path, expected_value, actual_value, differences),
+                    None => differences.push(format!("{path}: missing, expected {expected_value}")),
+                }
+            }
+            for (key, actual_value) in actual {
+                if !expected.contains_key(key) {
+                    differences.push(format!("{path}.{key}: unexpected {actual_value}"));
+                }
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            for (index, expected_value) in expected.iter().enumerate() {
+                let path = format!("{path}[{index}]");
+                match actual.get(index) {
+                    Some(actual_value) => diff_at(&path, expected_value, actual_value, differences),
+                    None => differences.push(format!("{path}: missing, expected {expected_value}")),
+                }
+            }
+            for (index, actual_value) in actual.iter().enumerate().skip(expected.len()) {
+                differences.push(format!("{path}[{index}]: unexpected {actual_value}"));
+            }
+        }
+        _ if same_value(expected, actual) => {}
+        _ => differences.push(format!("{path}: expected {expected}, got {actual}")),
+    }
+}
+
+fn same_value(expected: &Value, actual: &Value) -> bool {
+    if expected == actual {
+        return true;
+    }
+    match (as_number(expected), as_number(actual)) {
+        (Some(expected), Some(actual)) => expected == actual,
+        _ => false,
+    }
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(string) => string.parse().ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reports_differences_with_their_path() {
+        let expected = json!({
+            "operations": [
+                {"message": "10% OFF", "value": "10"},
+                {"message": "FREE SHIPPING"}
+            ]
+        });
+        let actual = json!({
+            "operations": [
+                {"message": "20% OFF", "value": "10.0", "extra": true}
+            ]
+        });
+
+        assert_eq!(
+            diff(&expected, &actual),
+            vec![
+                "$.operations[0].message: expected \"10% OFF\", got \"20% OFF\"",
+                "$.operations[0].extra: unexpected true",
+                "$.operations[1]: missing, expected {\"message\":\"FREE SHIPPING\"}",
+            ]
+        );
+    }
+
+    #[test]
+    fn equal_values_have_no_differences() {
+        let value = json!({"operations": [{"value": "10.0"}]});
+
+        assert!(diff(&value, &value).is_empty());
+    }
+}
@@ -0,0 +1,44 @@
+//! Development tools shared by the Rust discount function examples.
+//!
+//! These helpers only run natively, in tests and on developer machines, and are
+//! never compiled into the Wasm module deployed to Shopify.
+
+pub mod budgets;
+pub mod cart_builder;
+pub mod differential;
+pub mod fixtures;
+pub mod fuzz;
+pub mod json_diff;
+pub mod properties;
+pub mod replay;
+pub mod runner;
+pub mod snapshots;
+
+use shopify_function::run_function_with_input;
+use shopify_function::wasm_api::{Context, Deserialize, Serialize};
+
+/// Runs `function` with the JSON `input` and returns its output as JSON. A
+/// function error is returned as `{"error": "<message>"}`, so that expected
+/// failures can be described in the same format as successful outputs.
+pub fn run_to_json<F, P, O>(function: F, input: &str) -> serde_json::Value
+where
+    F: Fn(P) -> shopify_function::Result<O>,
+    P: Deserialize,
+    O: Serialize,
+{
+    match run_function_with_input(function, input) {
+        Ok(output) => output_to_json(&output),
+        Err(error) => serde_json::json!({ "error": error.to_string() }),
+    }
+}
+
+/// Serializes a function output the same way it is sent to Shopify.
+pub fn output_to_json<O: Serialize>(output: &O) -> serde_json::Value {
+    let mut context = Context::new_with_input(serde_json::Value::Null);
+    output
+        .serialize(&mut context)
+        .expect("Failed to serialize output");
+    context
+        .finalize_output_and_return()
+        .expect("Failed to finalize output")
+}
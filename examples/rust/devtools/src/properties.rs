@@ -0,0 +1,218 @@
+//! Strategies and invariants for property-based tests of the function targets.
+//!
+//! The strategies generate the JSON input Shopify sends to a target. Fields
+//! that a target's input query doesn't select are ignored when the input is
+//! deserialized, so the same generated carts work for every example. The
+//! invariants are checked on the JSON output, so they don't depend on the types
+//! generated for each example.
+
+use proptest::prelude::*;
+use proptest::test_runner::TestCaseError;
+use serde_json::{json, Value};
+
+pub const DISCOUNT_CLASSES: [&str; 3] = ["PRODUCT", "ORDER", "SHIPPING"];
+/// The collection of the discount, for the `inCollections` of generated cart
+/// lines.
+pub const COLLECTION_ID: &str = "gid://shopify/Collection/1";
+
+/// Any subset of the discount classes, including none.
+pub fn discount_classes() -> impl Strategy<Value = Vec<&'static str>> {
+    proptest::sample::subsequence(DISCOUNT_CLASSES.to_vec(), 0..=DISCOUNT_CLASSES.len())
+}
+
+/// A `Decimal` amount as Shopify sends it. Zero and `NaN` are included, because
+/// both parse as valid decimals.
+pub fn amount() -> impl Strategy<Value = String> {
+    prop_oneof![
+        8 => (0u32..100_000).prop_map(|cents| format!("{}.{:02}", cents / 100, cents % 100)),
+        1 => Just("0.0".to_string()),
+        1 => Just("NaN".to_string()),
+    ]
+}
+
+/// A percentage configured by a merchant, including values outside of
+/// `[0, 100]` that the targets must not pass on to Shopify.
+pub fn configured_percentage() -> impl Strategy<Value = f64> {
+    prop_oneof![
+        Just(0.0),
+        Just(100.0),
+        -100.0..0.0f64,
+        0.0..=100.0f64,
+        100.0..1_000.0f64,
+    ]
+}
+
+/// Cart lines with unique IDs. Each line is a product variant that may or may
+/// not be in the discount's collections, which are `COLLECTION_ID`.
+pub fn cart_lines() -> impl Strategy<Value = Vec<Value>> {
+    prop::collection::vec((amount(), 1..10i32, any::<bool>()), 0..8).prop_map(|lines| {
+        lines
+            .into_iter()
+            .enumerate()
+            .map(|(index, (amount, quantity, in_any_collection))| {
+                json!({
+                    "id": format!("gid://shopify/CartLine/{index}"),
+                    "quantity": quantity,
+                    "cost": {
+                        "subtotalAmount": {
+                            "amount": amount
+                        }
+                    },
+                    "merchandise": {
+                        "__typename": "ProductVariant",
+                        "product": {
+                            "inAnyCollection": in_any_collection,
+                            "inCollections": [{
+                                "collectionId": COLLECTION_ID,
+                                "isMember": in_any_collection
+                            }],
+                            "hasTags": []
+                        }
+                    }
+                })
+            })
+            .collect()
+    })
+}
+
+/// Delivery groups with unique IDs, each with at least one delivery option.
+pub fn delivery_groups() -> impl Strategy<Value = Vec<Value>> {
+    prop::collection::vec(prop::collection::vec(amount(), 1..4), 0..4).prop_map(|groups| {
+        groups
+            .into_iter()
+            .enumerate()
+            .map(|(index, option_amounts)| {
+                let delivery_options: Vec<Value> = option_amounts
+                    .into_iter()
+                    .enumerate()
+                    .map(|(option_index, amount)| {
+                        json!({
+                            "handle": format!("option-{option_index}"),
+                            "cost": {
+                                "amount": amount,
+                                "currencyCode": "USD"
+                            }
+                        })
+                    })
+                    .collect();
+                json!({
+                    "id": format!("gid://shopify/CartDeliveryGroup/{index}"),
+                    "deliveryOptions": delivery_options
+                })
+            })
+            .collect()
+    })
+}
+
+/// Checks the invariants every run target must hold, whatever the cart and
+/// configuration:
+///
+/// - Candidates only target cart lines, delivery groups and delivery options
+///   that are in the input.
+/// - Percentages are within `[0, 100]`.
+/// - A discount without any discount class returns no operations.
+pub fn check_run_invariants(input: &Value, output: &Value) -> Result<(), TestCaseError> {
+    let line_ids = ids(&input["cart"]["lines"]);
+    for id in targeted_ids(output, "cartLine") {
+        prop_assert!(
+            line_ids.contains(&id),
+            "Candidate targets cart line {id}, which isn't in the input"
+        );
+    }
+
+    let delivery_group_ids = ids(&input["cart"]["deliveryGroups"]);
+    for id in targeted_ids(output, "deliveryGroup") {
+        prop_assert!(
+            delivery_group_ids.contains(&id),
+            "Candidate targets delivery group {id}, which isn't in the input"
+        );
+    }
+
+    let delivery_option_handles: Vec<&str> = input["cart"]["deliveryGroups"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|delivery_group| delivery_group["deliveryOptions"].as_array())
+        .flatten()
+        .filter_map(|delivery_option| delivery_option["handle"].as_str())
+        .collect();
+    for handle in targeted_fields(output, "deliveryOption", "handle") {
+        prop_assert!(
+            delivery_option_handles.contains(&handle),
+            "Candidate targets delivery option {handle}, which isn't in the input"
+        );
+    }
+
+    for percentage in percentages(output) {
+        prop_assert!(
+            (0.0..=100.0).contains(&percentage),
+            "Percentage {percentage} is outside of [0, 100]"
+        );
+    }
+
+    let has_discount_classes = input["discount"]["discountClasses"]
+        .as_array()
+        .is_some_and(|discount_classes| !discount_classes.is_empty());
+    if !has_discount_classes {
+        prop_assert_eq!(output, &json!({ "operations": [] }));
+    }
+
+    Ok(())
+}
+
+/// The IDs of every target of the given kind, for example `cartLine`.
+pub fn targeted_ids<'a>(output: &'a Value, target: &str) -> Vec<&'a str> {
+    targeted_fields(output, target, "id")
+}
+
+/// The `field` of every target of the given kind.
+fn targeted_fields<'a>(output: &'a Value, target: &str, field: &str) -> Vec<&'a str> {
+    let mut targets = vec![];
+    find(output, target, &mut targets);
+    targets
+        .into_iter()
+        .filter_map(|target| target[field].as_str())
+        .collect()
+}
+
+/// The value of every percentage in the output.
+pub fn percentages(output: &Value) -> Vec<f64> {
+    let mut percentages = vec![];
+    find(output, "percentage", &mut percentages);
+    percentages
+        .into_iter()
+        .map(|percentage| match &percentage["value"] {
+            Value::String(value) => value.parse().unwrap_or(f64::NAN),
+            value => value.as_f64().unwrap_or(f64::NAN),
+        })
+        .collect()
+}
+
+fn ids(items: &Value) -> Vec<&str> {
+    items
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| item["id"].as_str())
+        .collect()
+}
+
+/// Collects the values of every `key` in `value`, at any depth.
+fn find<'a>(value: &'a Value, key: &str, found: &mut Vec<&'a Value>) {
+    match value {
+        Value::Object(object) => {
+            for (name, value) in object {
+                if name == key {
+                    found.push(value);
+                }
+                find(value, key, found);
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                find(value, key, found);
+            }
+        }
+        _ => {}
+    }
+}
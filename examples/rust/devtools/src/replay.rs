@@ -0,0 +1,193 @@
+//! Replays function run logs through the current build.
+//!
+//! Each `*.json` file in a log directory is one function run, as downloaded
+//! from the Partner Dashboard or written to `.shopify/logs` by the Shopify
+//! CLI. The export, input and output are read from the `payload` of the log,
+//! or from the top level of the file:
+//!
+//! ```json
+//! {
+//!   "payload": {
+//!     "export": "cart_lines_discounts_generate_run",
+//!     "input": { "cart": { "lines": [] } },
+//!     "output": { "operations": [] }
+//!   }
+//! }
+//! ```
+//!
+//! Replaying a log runs its input through the export and compares the result
+//! with the logged output, so a change can be checked against real checkouts
+//! before it's deployed.
+
+use crate::json_diff;
+use crate::runner::Export;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// A function run read from a log file.
+pub struct FunctionRunLog {
+    pub name: String,
+    pub export: String,
+    pub input: Value,
+    pub output: Value,
+}
+
+/// The result of replaying a directory of logs.
+#[derive(Default)]
+pub struct ReplayReport {
+    pub replayed: usize,
+    /// Logs that can't be replayed, with the reason.
+    pub skipped: Vec<String>,
+    /// Logs whose output changed, with the differences.
+    pub changed: Vec<String>,
+}
+
+impl ReplayReport {
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{} replayed, {} changed, {} skipped",
+            self.replayed,
+            self.changed.len(),
+            self.skipped.len()
+        );
+        for skipped in &self.skipped {
+            summary.push_str(&format!("\n\nskipped {skipped}"));
+        }
+        for changed in &self.changed {
+            summary.push_str(&format!("\n\n{changed}"));
+        }
+        summary
+    }
+}
+
+/// Replays every log in `dir` through `exports`.
+pub fn replay(dir: impl AsRef<Path>, exports: &[Export]) -> Result<ReplayReport, String> {
+    let dir = dir.as_ref();
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .map_err(|error| format!("Failed to read logs in {}: {error}", dir.display()))?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    paths.sort();
+
+    let mut report = ReplayReport::default();
+    for path in paths {
+        let name = path.display().to_string();
+        let log = match read_log(&path) {
+            Ok(log) => log,
+            Err(reason) => {
+                report.skipped.push(format!("{name}: {reason}"));
+                continue;
+            }
+        };
+        let Some((_, run_export)) = exports.iter().find(|(export, _)| *export == log.export) else {
+            report
+                .skipped
+                .push(format!("{name}: unknown export {}", log.export));
+            continue;
+        };
+
+        let output = run_export(&log.input.to_string());
+        let differences = json_diff::diff(&log.output, &output);
+        if !differences.is_empty() {
+            report
+                .changed
+                .push(format!("{name}:\n    {}", differences.join("\n    ")));
+        }
+        report.replayed += 1;
+    }
+    Ok(report)
+}
+
+/// Replays every log in `dir` and fails when an output changed.
+pub fn assert_replays(dir: impl AsRef<Path>, exports: &[Export]) {
+    let report = replay(dir, exports).unwrap_or_else(|error| panic!("{error}"));
+    assert!(report.changed.is_empty(), "{}", report.summary());
+}
+
+pub fn read_log(path: &Path) -> Result<FunctionRunLog, String> {
+    let log = fs::read_to_string(path).map_err(|error| format!("failed to read: {error}"))?;
+    let log: Value =
+        serde_json::from_str(&log).map_err(|error| format!("invalid JSON: {error}"))?;
+    let payload = log.get("payload").unwrap_or(&log);
+
+    let export = payload["export"]
+        .as_str()
+        .ok_or("missing export")?
+        .to_string();
+    let input = embedded_json(&payload["input"]).ok_or("missing input")?;
+    let output = embedded_json(&payload["output"]).ok_or("missing output, the run failed")?;
+
+    Ok(FunctionRunLog {
+        name: path.display().to_string(),
+        export,
+        input,
+        output,
+    })
+}
+
+/// Logs hold the input and output either as JSON or as a string of JSON.
+fn embedded_json(value: &Value) -> Option<Value> {
+    match value {
+        Value::Null => None,
+        Value::String(json) => serde_json::from_str(json).ok(),
+        value => Some(value.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::env;
+
+    const EXPORTS: &[Export] = &[("echo_run", |input| {
+        let input: Value = serde_json::from_str(input).unwrap();
+        json!({ "operations": input["operations"] })
+    })];
+
+    #[test]
+    fn reports_changed_outputs_and_skipped_logs() {
+        let dir = env::temp_dir().join(format!("devtools-replay-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, log: Value| {
+            fs::write(dir.join(name), log.to_string()).unwrap();
+        };
+        write(
+            "0-unchanged.json",
+            json!({
+                "payload": {
+                    "export": "echo_run",
+                    "input": "{\"operations\": [1]}",
+                    "output": { "operations": [1] }
+                }
+            }),
+        );
+        write(
+            "1-changed.json",
+            json!({
+                "export": "echo_run",
+                "input": { "operations": [2] },
+                "output": { "operations": [3] }
+            }),
+        );
+        write(
+            "2-unknown-export.json",
+            json!({ "export": "other_run", "input": {}, "output": {} }),
+        );
+
+        let report = replay(&dir, EXPORTS).unwrap();
+
+        assert_eq!(report.replayed, 2);
+        assert_eq!(report.skipped.len(), 1);
+        assert!(report.skipped[0].ends_with("unknown export other_run"));
+        assert_eq!(report.changed.len(), 1);
+        assert!(report.changed[0].ends_with("$.operations[0]: expected 3, got 2"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
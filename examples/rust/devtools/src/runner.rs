@@ -0,0 +1,329 @@
+//! A command-line runner for the targets of an example.
+//!
+//! The native build of each example runs a named export with a JSON input and
+//! prints the output, so that rules and configurations can be tried offline,
+//! without the Shopify CLI:
+//!
+//! ```sh
+//! cargo run -- cart_lines_discounts_generate_run input.json \
+//!     --metafield '{"cartLinePercentage": 20}' \
+//!     --fetch-response response.json \
+//!     --explain
+//! cargo run -- replay logs/
+//! ```
+
+use crate::replay;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process;
+
+pub const USAGE: &str = "\
+Usage: discount-function-rs <export> [input.json] [options]
+       discount-function-rs replay <log directory>
+
+Runs a named export with the input from input.json, or from stdin when no file
+is given, and prints the output.
+
+The replay command runs the input of every function run log in the directory,
+and reports the runs whose output changed.
+
+Options:
+  --metafield <json or file>   Replaces the jsonValue of the discount's metafield
+  --fetch-response <file>      Sets the fetch result of a run target to a
+                               successful response with this JSON body
+  --explain                    Prints the output with the explain trace of the
+                               run, describing why each discount was or wasn't
+                               applied";
+
+/// A named export of an example and a function running it with a JSON input.
+pub type Export = (&'static str, fn(&str) -> serde_json::Value);
+
+/// Takes the explain trace recorded by the exports since it was last taken.
+pub type TakeTrace = fn() -> Vec<String>;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Options {
+    pub export: String,
+    pub input_path: Option<String>,
+    pub metafield: Option<String>,
+    pub fetch_response_path: Option<String>,
+    pub explain: bool,
+}
+
+/// Runs the export named in the command-line arguments and exits. The output
+/// is printed to stdout, and errors to stderr with a non-zero exit code.
+pub fn main(exports: &[Export], take_trace: TakeTrace) -> ! {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let [command, dir] = args.as_slice() {
+        if command == "replay" {
+            replay_main(exports, dir);
+        }
+    }
+
+    match run(exports, take_trace, args) {
+        Ok(output) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&output).expect("Failed to print output")
+            );
+            process::exit(0);
+        }
+        Err(message) => {
+            eprintln!("{message}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Replays the logs in `dir`, and exits with a non-zero exit code when an output
+/// changed.
+fn replay_main(exports: &[Export], dir: &str) -> ! {
+    match replay::replay(dir, exports) {
+        Ok(report) => {
+            println!("{}", report.summary());
+            process::exit(if report.changed.is_empty() { 0 } else { 1 });
+        }
+        Err(message) => {
+            eprintln!("{message}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Runs the export named in `args`. With `--explain`, the output is returned
+/// as `{"output": ..., "explain": [...]}`.
+pub fn run(
+    exports: &[Export],
+    take_trace: TakeTrace,
+    args: impl IntoIterator<Item = String>,
+) -> Result<serde_json::Value, String> {
+    let options = parse_args(args)?;
+    let export_names: Vec<&str> = exports.iter().map(|(name, _)| *name).collect();
+    let (_, run_export) = exports
+        .iter()
+        .find(|(name, _)| *name == options.export)
+        .ok_or_else(|| {
+            format!(
+                "Unknown export {}. Available exports:\n  {}",
+                options.export,
+                export_names.join("\n  ")
+            )
+        })?;
+
+    let input = match &options.input_path {
+        Some(path) => read(path)?,
+        None => {
+            let mut input = String::new();
+            io::stdin()
+                .read_to_string(&mut input)
+                .map_err(|error| format!("Failed to read stdin: {error}"))?;
+            input
+        }
+    };
+    let input = prepare_input(&options, &input)?;
+
+    take_trace();
+    let output = run_export(&input.to_string());
+    let trace = take_trace();
+    match output.get("error") {
+        Some(error) if options.explain => Err(format!(
+            "{} failed: {}\n\nexplain:\n  {}",
+            options.export,
+            error,
+            trace.join("\n  ")
+        )),
+        Some(error) => Err(format!("{} failed: {}", options.export, error)),
+        None if options.explain => Ok(serde_json::json!({ "output": output, "explain": trace })),
+        None => Ok(output),
+    }
+}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {option}\n\n{USAGE}"))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            "--metafield" => options.metafield = Some(value(&arg)?),
+            "--fetch-response" => options.fetch_response_path = Some(value(&arg)?),
+            "--explain" => options.explain = true,
+            option if option.starts_with("--") => {
+                return Err(format!("Unknown option {option}\n\n{USAGE}"))
+            }
+            _ if options.export.is_empty() => options.export = arg,
+            _ if options.input_path.is_none() => options.input_path = Some(arg),
+            _ => return Err(format!("Unexpected argument {arg}\n\n{USAGE}")),
+        }
+    }
+
+    if options.export.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    if options.fetch_response_path.is_some() && !options.export.ends_with("_run") {
+        return Err("--fetch-response only applies to run targets".to_string());
+    }
+    Ok(options)
+}
+
+/// Applies the metafield and fetch response overrides to the input.
+pub fn prepare_input(options: &Options, input: &str) -> Result<serde_json::Value, String> {
+    let mut input = parse(input, "the input")?;
+
+    if let Some(metafield) = &options.metafield {
+        let configuration = if Path::new(metafield).is_file() {
+            parse(&read(metafield)?, metafield)?
+        } else {
+            parse(metafield, "--metafield")?
+        };
+        let discount = input
+            .get_mut("discount")
+            .and_then(serde_json::Value::as_object_mut)
+            .ok_or("--metafield needs an input with a `discount` object")?;
+        discount.insert(
+            "metafield".to_string(),
+            serde_json::json!({ "jsonValue": configuration }),
+        );
+    }
+
+    if let Some(path) = &options.fetch_response_path {
+        let json_body = parse(&read(path)?, path)?;
+        let input = input
+            .as_object_mut()
+            .ok_or("--fetch-response needs an input object")?;
+        input.insert(
+            "fetchResult".to_string(),
+            serde_json::json!({
+                "status": 200,
+                "jsonBody": json_body
+            }),
+        );
+    }
+
+    Ok(input)
+}
+
+fn read(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|error| format!("Failed to read {path}: {error}"))
+}
+
+fn parse(json: &str, source: &str) -> Result<serde_json::Value, String> {
+    serde_json::from_str(json).map_err(|error| format!("Invalid JSON in {source}: {error}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_export_input_and_options() {
+        let options = parse_args(args(&[
+            "cart_lines_discounts_generate_run",
+            "input.json",
+            "--metafield",
+            "{}",
+            "--fetch-response",
+            "response.json",
+            "--explain",
+        ]));
+
+        assert_eq!(
+            options,
+            Ok(Options {
+                export: "cart_lines_discounts_generate_run".to_string(),
+                input_path: Some("input.json".to_string()),
+                metafield: Some("{}".to_string()),
+                fetch_response_path: Some("response.json".to_string()),
+                explain: true,
+            })
+        );
+        assert_eq!(
+            parse_args(args(&[
+                "cart_lines_discounts_generate_fetch",
+                "--fetch-response",
+                "response.json"
+            ])),
+            Err("--fetch-response only applies to run targets".to_string())
+        );
+    }
+
+    #[test]
+    fn replaces_metafield_of_the_input() {
+        let options = Options {
+            export: "cart_lines_discounts_generate_run".to_string(),
+            metafield: Some(r#"{"cartLinePercentage": 20}"#.to_string()),
+            ..Options::default()
+        };
+
+        let input = prepare_input(
+            &options,
+            r#"{"discount": {"discountClasses": ["PRODUCT"], "metafield": null}}"#,
+        );
+
+        assert_eq!(
+            input,
+            Ok(json!({
+                "discount": {
+                    "discountClasses": ["PRODUCT"],
+                    "metafield": { "jsonValue": { "cartLinePercentage": 20 } }
+                }
+            }))
+        );
+    }
+
+    #[test]
+    fn rejects_inputs_without_a_discount_object() {
+        let options = Options {
+            export: "cart_lines_discounts_generate_run".to_string(),
+            metafield: Some("{}".to_string()),
+            ..Options::default()
+        };
+
+        for input in ["[]", "{}", r#"{"discount": "PRODUCT"}"#] {
+            assert_eq!(
+                prepare_input(&options, input),
+                Err("--metafield needs an input with a `discount` object".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn returns_explain_trace_with_the_output() {
+        thread_local! {
+            static TRACE: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(vec![]) };
+        }
+        const EXPORTS: &[Export] = &[("explained_run", |_| {
+            TRACE.with_borrow_mut(|trace| trace.push("no discount classes".to_string()));
+            json!({ "operations": [] })
+        })];
+        let take_trace: TakeTrace = || TRACE.take();
+        let input = std::env::temp_dir().join(format!("devtools-explain-{}.json", process::id()));
+        fs::write(&input, "{}").unwrap();
+        TRACE.with_borrow_mut(|trace| trace.push("from an earlier run".to_string()));
+
+        let output = run(
+            EXPORTS,
+            take_trace,
+            args(&["explained_run", input.to_str().unwrap(), "--explain"]),
+        );
+
+        assert_eq!(
+            output,
+            Ok(json!({
+                "output": { "operations": [] },
+                "explain": ["no discount classes"]
+            }))
+        );
+        fs::remove_file(input).unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+//! Snapshot testing of function outputs.
+//!
+//! A snapshot is the complete output of a target, written as pretty-printed
+//! JSON with sorted keys, so that any change to the output, including fields a
+//! test doesn't look at, shows up in review.
+//!
+//! When an output no longer matches its snapshot, or the snapshot doesn't exist
+//! yet, the test fails and the new output is written next to the snapshot with
+//! a `.new` suffix for review. Changes are accepted by running the tests again
+//! with `UPDATE_SNAPSHOTS=1`, which overwrites the snapshots, and reviewing the
+//! resulting `git diff`.
+
+use crate::{json_diff, output_to_json};
+use shopify_function::wasm_api::Serialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Environment variable accepting all changed and new snapshots.
+pub const UPDATE_SNAPSHOTS_ENV: &str = "UPDATE_SNAPSHOTS";
+
+/// Asserts that the output of a target matches the snapshot stored at
+/// `tests/snapshots/<name>.snap.json` in the calling crate.
+#[macro_export]
+macro_rules! assert_snapshot {
+    ($name:expr, $output:expr) => {
+        $crate::snapshots::assert_output_snapshot(
+            ::std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/snapshots")
+                .join(format!("{}.snap.json", $name)),
+            $output,
+        )
+    };
+}
+
+/// Asserts that `output` matches the snapshot at `path`.
+pub fn assert_output_snapshot<O: Serialize>(path: impl AsRef<Path>, output: &O) {
+    if let Err(failure) = check_snapshot(path.as_ref(), &output_to_json(output)) {
+        panic!("{failure}");
+    }
+}
+
+/// Compares `actual` with the snapshot at `path`, returning a description of
+/// the differences when they don't match. With `UPDATE_SNAPSHOTS=1`, the
+/// snapshot is overwritten instead.
+pub fn check_snapshot(path: &Path, actual: &serde_json::Value) -> Result<(), String> {
+    let pending_path = pending_path(path);
+
+    if update_snapshots() {
+        write(path, actual);
+        let _ = fs::remove_file(&pending_path);
+        return Ok(());
+    }
+
+    let differences = match fs::read_to_string(path) {
+        Ok(snapshot) => {
+            let expected: serde_json::Value = serde_json::from_str(&snapshot)
+                .unwrap_or_else(|error| panic!("Invalid JSON in {}: {error}", path.display()));
+            json_diff::diff(&expected, actual)
+        }
+        Err(_) => vec![format!("{} doesn't exist yet", path.display())],
+    };
+
+    if differences.is_empty() {
+        let _ = fs::remove_file(&pending_path);
+        return Ok(());
+    }
+
+    write(&pending_path, actual);
+    Err(format!(
+        "Snapshot {} doesn't match:\n    {}\nThe new output was written to {}. Run the tests with {UPDATE_SNAPSHOTS_ENV}=1 to accept it.",
+        path.display(),
+        differences.join("\n    "),
+        pending_path.display()
+    ))
+}
+
+/// Stable, pretty-printed JSON. `serde_json` keeps object keys sorted.
+pub fn to_snapshot_string(value: &serde_json::Value) -> String {
+    let mut snapshot = serde_json::to_string_pretty(value).expect("Failed to serialize snapshot");
+    snapshot.push('\n');
+    snapshot
+}
+
+fn update_snapshots() -> bool {
+    env::var(UPDATE_SNAPSHOTS_ENV).is_ok_and(|value| value == "1")
+}
+
+fn pending_path(path: &Path) -> PathBuf {
+    let mut pending_path = path.as_os_str().to_owned();
+    pending_path.push(".new");
+    PathBuf::from(pending_path)
+}
+
+fn write(path: &Path, value: &serde_json::Value) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .unwrap_or_else(|error| panic!("Failed to create {}: {error}", parent.display()));
+    }
+    fs::write(path, to_snapshot_string(value))
+        .unwrap_or_else(|error| panic!("Failed to write {}: {error}", path.display()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn writes_pending_snapshot_on_mismatch() {
+        let dir = env::temp_dir().join(format!("devtools-snapshots-{}", std::process::id()));
+        let path = dir.join("output.snap.json");
+        write(&path, &json!({"operations": []}));
+
+        let actual = json!({"operations": [{"message": "10% OFF"}]});
+        let failure = check_snapshot(&path, &actual).expect_err("Expected a mismatch");
+
+        assert!(failure.contains("$.operations[0]: unexpected"));
+        assert_eq!(
+            fs::read_to_string(pending_path(&path)).unwrap(),
+            to_snapshot_string(&actual)
+        );
+        assert_eq!(check_snapshot(&path, &json!({"operations": []})), Ok(()));
+        assert!(!pending_path(&path).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
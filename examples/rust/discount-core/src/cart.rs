@@ -0,0 +1,29 @@
+//! Helpers reading the cart lines of an input.
+
+use crate::money::Money;
+
+/// The line with the largest `subtotal_amount`. Lines without an amount, such
+/// as the ones whose amount isn't a number, are only picked when no line has
+/// one.
+pub fn most_expensive_line<L>(
+    lines: &[L],
+    subtotal_amount: impl Fn(&L) -> Option<Money>,
+) -> Option<&L> {
+    // `None` compares below every amount
+    lines.iter().max_by_key(|line| subtotal_amount(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shopify_function::scalars::Decimal;
+
+    #[test]
+    fn picks_the_most_expensive_line() {
+        let lines = [("0", 10.0), ("1", f64::NAN), ("2", 30.0), ("3", 20.0)];
+
+        let line = most_expensive_line(&lines, |(_, amount)| Money::from_decimal(Decimal(*amount)));
+
+        assert_eq!(line, Some(&("2", 30.0)));
+    }
+}
@@ -0,0 +1,72 @@
+//! The discount classes a run target is allowed to return discounts for.
+
+use std::fmt;
+
+/// The discount classes of a discount, from the `discountClasses` of a
+/// target's input. Unknown classes are ignored.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DiscountClasses {
+    pub order: bool,
+    pub product: bool,
+    pub shipping: bool,
+}
+
+impl DiscountClasses {
+    pub fn new(discount_classes: &[impl AsRef<str>]) -> Self {
+        let has = |name: &str| {
+            discount_classes
+                .iter()
+                .any(|discount_class| discount_class.as_ref() == name)
+        };
+        Self {
+            order: has("ORDER"),
+            product: has("PRODUCT"),
+            shipping: has("SHIPPING"),
+        }
+    }
+
+    /// Whether the cart lines targets can return any discount.
+    pub fn has_cart_lines_class(&self) -> bool {
+        self.order || self.product
+    }
+}
+
+impl fmt::Display for DiscountClasses {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = [
+            (self.order, "ORDER"),
+            (self.product, "PRODUCT"),
+            (self.shipping, "SHIPPING"),
+        ]
+        .into_iter()
+        .filter_map(|(has_class, name)| has_class.then_some(name))
+        .collect();
+        if names.is_empty() {
+            f.write_str("none")
+        } else {
+            f.write_str(&names.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_known_discount_classes() {
+        let discount_classes = DiscountClasses::new(&["SHIPPING", "PRODUCT", "UNKNOWN"]);
+
+        assert_eq!(
+            discount_classes,
+            DiscountClasses {
+                order: false,
+                product: true,
+                shipping: true,
+            }
+        );
+        assert!(discount_classes.has_cart_lines_class());
+        assert_eq!(discount_classes.to_string(), "PRODUCT, SHIPPING");
+        assert_eq!(DiscountClasses::new(&[] as &[&str]).to_string(), "none");
+    }
+}
@@ -0,0 +1,166 @@
+//! Errors of the targets, with stable codes, the target and field they come
+//! from, and what the target does about them.
+//!
+//! The message of an error is what Shopify shows when the function fails,
+//! while [`DiscountError::details`] adds the code and context for the logs:
+//!
+//! ```text
+//! MISSING_METAFIELD in cart_lines_discounts_generate_run at discount.metafield: No metafield provided
+//! ```
+
+use std::fmt;
+
+/// What a target does when it meets an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// The function fails, and Shopify applies none of its discounts.
+    Fail,
+    /// The target returns no operations for the part that failed, and applies
+    /// the rest.
+    Degrade,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    /// The cart has no lines to discount.
+    NoCartLines,
+    /// The cart has no delivery groups to discount.
+    NoDeliveryGroups,
+    /// The discount has no configuration metafield.
+    MissingMetafield,
+    /// A field of the configuration can't be applied.
+    InvalidConfiguration { field: String, reason: String },
+    /// The request to the external server failed.
+    FetchFailed { status: i32 },
+    /// The external server's response isn't in the expected shape.
+    MalformedFetchResponse { reason: String },
+}
+
+impl ErrorKind {
+    /// The code of the error, which stays the same across versions.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NoCartLines => "NO_CART_LINES",
+            Self::NoDeliveryGroups => "NO_DELIVERY_GROUPS",
+            Self::MissingMetafield => "MISSING_METAFIELD",
+            Self::InvalidConfiguration { .. } => "INVALID_CONFIGURATION",
+            Self::FetchFailed { .. } => "FETCH_FAILED",
+            Self::MalformedFetchResponse { .. } => "MALFORMED_FETCH_RESPONSE",
+        }
+    }
+
+    /// The field of the input, or of the configuration, with the error.
+    pub fn field(&self) -> &str {
+        match self {
+            Self::NoCartLines => "cart.lines",
+            Self::NoDeliveryGroups => "cart.deliveryGroups",
+            Self::MissingMetafield => "discount.metafield",
+            Self::InvalidConfiguration { field, .. } => field,
+            Self::FetchFailed { .. } => "fetchResult.status",
+            Self::MalformedFetchResponse { .. } => "fetchResult.jsonBody",
+        }
+    }
+
+    pub fn policy(&self) -> ErrorPolicy {
+        match self {
+            Self::NoCartLines | Self::NoDeliveryGroups => ErrorPolicy::Fail,
+            Self::MissingMetafield
+            | Self::InvalidConfiguration { .. }
+            | Self::FetchFailed { .. }
+            | Self::MalformedFetchResponse { .. } => ErrorPolicy::Degrade,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoCartLines => f.write_str("No cart lines found"),
+            Self::NoDeliveryGroups => f.write_str("No delivery groups found"),
+            Self::MissingMetafield => f.write_str("No metafield provided"),
+            Self::InvalidConfiguration { reason, .. } => f.write_str(reason),
+            Self::FetchFailed { status } => write!(f, "Request failed with status {status}"),
+            Self::MalformedFetchResponse { reason } => write!(f, "Malformed response: {reason}"),
+        }
+    }
+}
+
+/// An error met by a target.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscountError {
+    pub target: &'static str,
+    pub kind: ErrorKind,
+}
+
+impl DiscountError {
+    pub fn new(target: &'static str, kind: ErrorKind) -> Self {
+        Self { target, kind }
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.kind.code()
+    }
+
+    pub fn policy(&self) -> ErrorPolicy {
+        self.kind.policy()
+    }
+
+    /// The error with its code, target and field, for the logs.
+    pub fn details(&self) -> String {
+        format!(
+            "{} in {} at {}: {}",
+            self.code(),
+            self.target,
+            self.kind.field(),
+            self.kind
+        )
+    }
+
+    /// Applies the error's policy: fails the function with the error, or
+    /// returns `degraded` instead.
+    pub fn handle<T>(self, degraded: T) -> shopify_function::Result<T> {
+        match self.policy() {
+            ErrorPolicy::Fail => Err(self.into()),
+            ErrorPolicy::Degrade => Ok(degraded),
+        }
+    }
+}
+
+impl fmt::Display for DiscountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.kind.fmt(f)
+    }
+}
+
+impl std::error::Error for DiscountError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fails_or_degrades_by_policy() {
+        let error = DiscountError::new("cart_lines_discounts_generate_run", ErrorKind::NoCartLines);
+        assert_eq!(
+            error.details(),
+            "NO_CART_LINES in cart_lines_discounts_generate_run at cart.lines: No cart lines found"
+        );
+        assert_eq!(
+            error.handle(0).map_err(|error| error.to_string()),
+            Err("No cart lines found".to_string())
+        );
+
+        let error = DiscountError::new(
+            "cart_lines_discounts_generate_run",
+            ErrorKind::FetchFailed { status: 500 },
+        );
+        assert_eq!(error.code(), "FETCH_FAILED");
+        assert_eq!(error.handle(0).ok(), Some(0));
+
+        let error = DiscountError::new(
+            "cart_lines_discounts_generate_run",
+            ErrorKind::MissingMetafield,
+        );
+        assert_eq!(error.policy(), ErrorPolicy::Degrade);
+    }
+}
@@ -0,0 +1,101 @@
+//! The explain trace of a run, describing why each discount was or wasn't
+//! applied.
+//!
+//! Each decision is written to stderr, which Shopify keeps in the function
+//! logs, prefixed with `explain:`. Writing costs instructions, and Shopify
+//! truncates long logs, so only the first [`MAX_LOGGED`] decisions of a run are
+//! written, and [`list`] writes the first [`MAX_LISTED`] items of a list. The
+//! native build also keeps the whole trace, so the command-line runner of the
+//! examples can return it with `--explain`.
+
+use std::cell::Cell;
+use std::fmt;
+
+/// The number of decisions of a run written to the function logs.
+pub const MAX_LOGGED: usize = 50;
+/// The number of items of a list written to the trace.
+pub const MAX_LISTED: usize = 10;
+
+thread_local! {
+    static LOGGED: Cell<usize> = const { Cell::new(0) };
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+thread_local! {
+    static TRACE: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(vec![]) };
+}
+
+// Exported under another name, since the crate root already has an `explain`
+// module, and re-exported here
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __explain {
+    ($($arg:tt)*) => {
+        $crate::explain::record(format_args!($($arg)*))
+    };
+}
+/// Records a decision made by a run target.
+pub use crate::__explain as explain;
+
+pub fn record(decision: fmt::Arguments) {
+    let logged = LOGGED.get();
+    if logged < MAX_LOGGED {
+        eprintln!("explain: {decision}");
+    } else if logged == MAX_LOGGED {
+        eprintln!("explain: more than {MAX_LOGGED} decisions, the rest aren't logged");
+    }
+    LOGGED.set(logged.saturating_add(1));
+    #[cfg(not(target_arch = "wasm32"))]
+    TRACE.with_borrow_mut(|trace| trace.push(decision.to_string()));
+}
+
+/// Takes the decisions recorded since the trace was last taken.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn take_trace() -> Vec<String> {
+    LOGGED.set(0);
+    TRACE.take()
+}
+
+/// Formats a list of items for the trace, or `none` when it's empty. Items
+/// past the first [`MAX_LISTED`] are counted rather than formatted.
+pub fn list<T: fmt::Debug>(items: impl IntoIterator<Item = T>) -> String {
+    let mut items = items.into_iter();
+    let listed: Vec<String> = items
+        .by_ref()
+        .take(MAX_LISTED)
+        .map(|item| format!("{item:?}"))
+        .collect();
+    let more = items.count();
+    if listed.is_empty() {
+        "none".to_string()
+    } else if more > 0 {
+        format!("{}, …and {more} more", listed.join(", "))
+    } else {
+        listed.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_the_first_items() {
+        assert_eq!(list(Vec::<u32>::new()), "none");
+        assert_eq!(list(["a", "b"]), "\"a\", \"b\"");
+        assert_eq!(list(1..=25), "1, 2, 3, 4, 5, 6, 7, 8, 9, 10, …and 15 more");
+    }
+
+    #[test]
+    fn keeps_the_whole_trace() {
+        take_trace();
+        for decision in 0..=MAX_LOGGED {
+            explain!("decision {decision}");
+        }
+
+        let trace = take_trace();
+
+        assert_eq!(trace.len(), MAX_LOGGED + 1);
+        assert_eq!(LOGGED.get(), 0);
+    }
+}
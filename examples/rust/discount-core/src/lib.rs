@@ -0,0 +1,24 @@
+//! Building blocks shared by the Rust discount function examples.
+//!
+//! The output types of every target are generated here, in [`schema`], so that
+//! the examples and the helpers build the same operations. Each example still
+//! generates the input types of its own queries, and reads the
+//! `DiscountClass` enum as strings, which [`DiscountClasses`] parses. The
+//! decisions of every target are recorded with [`explain!`](explain::explain).
+
+pub mod cart;
+pub mod classes;
+pub mod error;
+pub mod explain;
+pub mod money;
+pub mod operations;
+pub mod targets;
+
+pub use classes::DiscountClasses;
+pub use error::{DiscountError, ErrorKind};
+
+use shopify_function::typegen;
+
+// The examples share the same schema, kept up to date by the Shopify CLI
+#[typegen("../default/extensions/discount-function/schema.graphql")]
+pub mod schema {}
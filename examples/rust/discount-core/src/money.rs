@@ -0,0 +1,239 @@
+//! Exact money math on the amounts and percentages of a discount.
+//!
+//! Shopify's `Decimal` scalar and the metafield's numbers are read as `f64`,
+//! which can't hold most decimal amounts exactly, and can be NaN. [`Money`]
+//! and [`Percentage`] hold a fixed number of decimal places instead, so sums,
+//! percentages of amounts and comparisons are exact, and values that aren't
+//! numbers are rejected when they're read rather than compared.
+
+use shopify_function::scalars::Decimal;
+use shopify_function::wasm_api::{read::Error, Deserialize, Value};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, Sub};
+
+/// The number of units in 1, so values are exact to 6 decimal places.
+const SCALE: i64 = 1_000_000;
+
+/// An amount of money, exact to 6 decimal places.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Money(i64);
+
+/// A percentage, exact to 6 decimal places.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Percentage(i64);
+
+impl Money {
+    pub const ZERO: Self = Self(0);
+
+    /// The amount of a number, or `None` when it isn't a finite number.
+    pub fn from_f64(amount: f64) -> Option<Self> {
+        scaled(amount).map(Self)
+    }
+
+    /// The amount of a `Decimal` scalar, or `None` when it isn't a finite
+    /// number.
+    pub fn from_decimal(amount: Decimal) -> Option<Self> {
+        Self::from_f64(amount.as_f64())
+    }
+
+    pub fn to_decimal(self) -> Decimal {
+        Decimal(unscaled(self.0))
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.0 > 0
+    }
+
+    /// `percentage` of the amount, rounded half away from zero.
+    pub fn percentage(self, percentage: Percentage) -> Self {
+        let amount = i128::from(self.0) * i128::from(percentage.0);
+        Self(saturated(rounded_div(amount, i128::from(100 * SCALE))))
+    }
+
+    /// The amount rounded half away from zero to the minor unit of a
+    /// currency, such as cents for `USD`.
+    pub fn rounded(self, currency_code: &str) -> Self {
+        let minor_unit = SCALE / 10_i64.pow(minor_units(currency_code));
+        Self(saturated(
+            rounded_div(i128::from(self.0), i128::from(minor_unit)) * i128::from(minor_unit),
+        ))
+    }
+}
+
+impl Percentage {
+    pub const HUNDRED: Self = Self::whole(100);
+
+    /// A whole number percentage, such as `Percentage::whole(20)` for 20%.
+    pub const fn whole(percentage: i64) -> Self {
+        Self(percentage * SCALE)
+    }
+
+    /// The percentage of a number, or `None` when it isn't a finite number.
+    pub fn from_f64(percentage: f64) -> Option<Self> {
+        scaled(percentage).map(Self)
+    }
+
+    /// The percentage of a `Decimal` scalar, or `None` when it isn't a finite
+    /// number.
+    pub fn from_decimal(percentage: Decimal) -> Option<Self> {
+        Self::from_f64(percentage.as_f64())
+    }
+
+    pub fn to_decimal(self) -> Decimal {
+        Decimal(unscaled(self.0))
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.0 > 0
+    }
+
+    /// Percentages above 100 can't be applied, so they're capped at 100.
+    pub fn capped(self) -> Self {
+        self.min(Self::HUNDRED)
+    }
+}
+
+impl Add for Money {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self(self.0.saturating_add(other.0))
+    }
+}
+
+impl Sub for Money {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self(self.0.saturating_sub(other.0))
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Self>>(amounts: I) -> Self {
+        amounts.fold(Self::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Money> for Money {
+    fn sum<I: Iterator<Item = &'a Self>>(amounts: I) -> Self {
+        amounts.copied().sum()
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_scaled(f, self.0)
+    }
+}
+
+impl fmt::Display for Percentage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_scaled(f, self.0)
+    }
+}
+
+impl Deserialize for Money {
+    fn deserialize(value: &Value) -> Result<Self, Error> {
+        Self::from_f64(Deserialize::deserialize(value)?).ok_or(Error::InvalidType)
+    }
+}
+
+impl Deserialize for Percentage {
+    fn deserialize(value: &Value) -> Result<Self, Error> {
+        Self::from_f64(Deserialize::deserialize(value)?).ok_or(Error::InvalidType)
+    }
+}
+
+/// The number of decimal places of a currency's minor unit, from ISO 4217.
+pub fn minor_units(currency_code: &str) -> u32 {
+    match currency_code {
+        "BIF" | "CLP" | "DJF" | "GNF" | "ISK" | "JPY" | "KMF" | "KRW" | "PYG" | "RWF" | "UGX"
+        | "VND" | "VUV" | "XAF" | "XOF" | "XPF" => 0,
+        "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => 3,
+        _ => 2,
+    }
+}
+
+fn scaled(value: f64) -> Option<i64> {
+    let scaled = (value * SCALE as f64).round();
+    // `i64::MAX as f64` rounds up to 2^63, which is out of range
+    (scaled.is_finite() && scaled.abs() < i64::MAX as f64).then_some(scaled as i64)
+}
+
+fn unscaled(value: i64) -> f64 {
+    value as f64 / SCALE as f64
+}
+
+fn rounded_div(dividend: i128, divisor: i128) -> i128 {
+    let quotient = dividend / divisor;
+    let remainder = dividend % divisor;
+    if remainder.abs() * 2 >= divisor {
+        quotient + dividend.signum()
+    } else {
+        quotient
+    }
+}
+
+fn saturated(value: i128) -> i64 {
+    value.clamp(i128::from(i64::MIN), i128::from(i64::MAX)) as i64
+}
+
+fn write_scaled(f: &mut fmt::Formatter, value: i64) -> fmt::Result {
+    let sign = if value < 0 { "-" } else { "" };
+    let value = value.unsigned_abs();
+    let (units, fraction) = (value / SCALE as u64, value % SCALE as u64);
+    if fraction == 0 {
+        write!(f, "{sign}{units}")
+    } else {
+        let fraction = format!("{fraction:06}");
+        write!(f, "{sign}{units}.{}", fraction.trim_end_matches('0'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(amount: f64) -> Money {
+        Money::from_f64(amount).unwrap()
+    }
+
+    #[test]
+    fn adds_decimal_amounts_exactly() {
+        assert_eq!(money(0.1) + money(0.2), money(0.3));
+        assert_eq!(
+            [money(19.99), money(0.01)].iter().sum::<Money>(),
+            money(20.0)
+        );
+        assert_eq!((money(0.1) + money(0.2)).to_string(), "0.3");
+        assert_eq!((money(0.1) + money(0.2)).to_decimal(), Decimal(0.3));
+    }
+
+    #[test]
+    fn takes_rounded_percentages_of_amounts() {
+        let percentage = Percentage::whole(15);
+
+        assert_eq!(money(19.99).percentage(percentage), money(2.9985));
+        assert_eq!(
+            money(19.99).percentage(percentage).rounded("USD"),
+            money(3.0)
+        );
+        assert_eq!(money(1234.5).rounded("JPY"), money(1235.0));
+        assert_eq!(money(1.2345).rounded("KWD"), money(1.235));
+        assert_eq!(money(-2.005).rounded("EUR"), money(-2.01));
+    }
+
+    #[test]
+    fn rejects_amounts_that_are_not_numbers() {
+        assert_eq!(Money::from_f64(f64::NAN), None);
+        assert_eq!(Money::from_f64(f64::INFINITY), None);
+        assert_eq!(Percentage::from_f64(1e300), None);
+        assert_eq!(
+            Percentage::from_f64(150.0).map(Percentage::capped),
+            Some(Percentage::HUNDRED)
+        );
+        assert_eq!(Percentage::from_f64(-5.5).unwrap().to_string(), "-5.5");
+    }
+}
@@ -0,0 +1,246 @@
+//! Builders for the discount operations returned by the run targets.
+
+use crate::money::Percentage;
+use crate::schema;
+use crate::targets;
+
+/// A product discount of `percentage` on each cart line in `targets`, or
+/// `None` when there's no target.
+pub fn product_percentage_discount(
+    targets: Vec<schema::ProductDiscountCandidateTarget>,
+    percentage: Percentage,
+    message: impl Into<String>,
+) -> Option<schema::CartOperation> {
+    product_discount(
+        targets,
+        schema::ProductDiscountCandidateValue::Percentage(percentage_value(percentage)),
+        message,
+    )
+}
+
+/// A product discount of `value` on the cart lines in `targets`, or `None`
+/// when there's no target.
+pub fn product_discount(
+    targets: Vec<schema::ProductDiscountCandidateTarget>,
+    value: schema::ProductDiscountCandidateValue,
+    message: impl Into<String>,
+) -> Option<schema::CartOperation> {
+    if targets.is_empty() {
+        return None;
+    }
+    Some(schema::CartOperation::ProductDiscountsAdd(
+        schema::ProductDiscountsAddOperation {
+            selection_strategy: schema::ProductDiscountSelectionStrategy::First,
+            candidates: vec![schema::ProductDiscountCandidate {
+                targets,
+                message: Some(message.into()),
+                value,
+                associated_discount_code: None,
+            }],
+        },
+    ))
+}
+
+/// An order discount of `percentage` on the order subtotal.
+pub fn order_percentage_discount(
+    percentage: Percentage,
+    message: impl Into<String>,
+) -> schema::CartOperation {
+    order_discount(
+        schema::OrderDiscountCandidateValue::Percentage(percentage_value(percentage)),
+        message,
+    )
+}
+
+/// An order discount of `value` on the order subtotal.
+pub fn order_discount(
+    value: schema::OrderDiscountCandidateValue,
+    message: impl Into<String>,
+) -> schema::CartOperation {
+    schema::CartOperation::OrderDiscountsAdd(schema::OrderDiscountsAddOperation {
+        selection_strategy: schema::OrderDiscountSelectionStrategy::First,
+        candidates: vec![schema::OrderDiscountCandidate {
+            targets: vec![targets::order_subtotal()],
+            message: Some(message.into()),
+            value,
+            conditions: None,
+            associated_discount_code: None,
+        }],
+    })
+}
+
+/// A delivery discount of `percentage` on a delivery group.
+pub fn delivery_group_percentage_discount(
+    delivery_group_id: impl Into<String>,
+    percentage: Percentage,
+    message: impl Into<String>,
+) -> schema::DeliveryOperation {
+    delivery_discounts(vec![delivery_candidate(
+        targets::delivery_group(delivery_group_id),
+        schema::DeliveryDiscountCandidateValue::Percentage(percentage_value(percentage)),
+        message,
+    )])
+}
+
+/// A delivery discount candidate of `value` on a single target.
+pub fn delivery_candidate(
+    target: schema::DeliveryDiscountCandidateTarget,
+    value: schema::DeliveryDiscountCandidateValue,
+    message: impl Into<String>,
+) -> schema::DeliveryDiscountCandidate {
+    schema::DeliveryDiscountCandidate {
+        targets: vec![target],
+        value,
+        message: Some(message.into()),
+        associated_discount_code: None,
+    }
+}
+
+/// Applies all of the delivery discount `candidates`.
+pub fn delivery_discounts(
+    candidates: Vec<schema::DeliveryDiscountCandidate>,
+) -> schema::DeliveryOperation {
+    schema::DeliveryOperation::DeliveryDiscountsAdd(schema::DeliveryDiscountsAddOperation {
+        selection_strategy: schema::DeliveryDiscountSelectionStrategy::All,
+        candidates,
+    })
+}
+
+/// Merges the candidates of the product discount operations into one operation,
+/// at the position of the first, so Shopify selects among them with
+/// `selection_strategy` instead of applying each of them.
+pub fn merge_product_discounts(
+    operations: Vec<schema::CartOperation>,
+    selection_strategy: schema::ProductDiscountSelectionStrategy,
+) -> Vec<schema::CartOperation> {
+    merge(
+        operations,
+        |operation| match operation {
+            schema::CartOperation::ProductDiscountsAdd(operation) => Ok(operation.candidates),
+            operation => Err(operation),
+        },
+        |candidates| {
+            schema::CartOperation::ProductDiscountsAdd(schema::ProductDiscountsAddOperation {
+                selection_strategy,
+                candidates,
+            })
+        },
+    )
+}
+
+/// Merges the candidates of the order discount operations into one operation,
+/// at the position of the first, so Shopify selects among them with
+/// `selection_strategy` instead of applying each of them.
+pub fn merge_order_discounts(
+    operations: Vec<schema::CartOperation>,
+    selection_strategy: schema::OrderDiscountSelectionStrategy,
+) -> Vec<schema::CartOperation> {
+    merge(
+        operations,
+        |operation| match operation {
+            schema::CartOperation::OrderDiscountsAdd(operation) => Ok(operation.candidates),
+            operation => Err(operation),
+        },
+        |candidates| {
+            schema::CartOperation::OrderDiscountsAdd(schema::OrderDiscountsAddOperation {
+                selection_strategy,
+                candidates,
+            })
+        },
+    )
+}
+
+/// Replaces the operations `candidates` takes the candidates of with a single
+/// operation built by `merged`.
+fn merge<C>(
+    operations: Vec<schema::CartOperation>,
+    candidates: impl Fn(schema::CartOperation) -> Result<Vec<C>, schema::CartOperation>,
+    merged: impl FnOnce(Vec<C>) -> schema::CartOperation,
+) -> Vec<schema::CartOperation> {
+    let mut merged_candidates = vec![];
+    let mut merged_index = None;
+    let mut others = vec![];
+    for operation in operations {
+        match candidates(operation) {
+            Ok(operation_candidates) => {
+                merged_index.get_or_insert(others.len());
+                merged_candidates.extend(operation_candidates);
+            }
+            Err(operation) => others.push(operation),
+        }
+    }
+    if let Some(index) = merged_index {
+        others.insert(index, merged(merged_candidates));
+    }
+    others
+}
+
+fn percentage_value(percentage: Percentage) -> schema::Percentage {
+    schema::Percentage {
+        value: percentage.to_decimal(),
+    }
+}
+
+impl schema::CartLinesDiscountsGenerateRunResult {
+    /// A result without any operation.
+    pub fn empty() -> Self {
+        Self { operations: vec![] }
+    }
+}
+
+impl schema::CartDeliveryOptionsDiscountsGenerateRunResult {
+    /// A result without any operation.
+    pub fn empty() -> Self {
+        Self { operations: vec![] }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_product_discounts_without_targets() {
+        let percentage = Percentage::whole(20);
+
+        assert_eq!(
+            product_percentage_discount(vec![], percentage, "20% OFF"),
+            None
+        );
+        assert!(matches!(
+            product_percentage_discount(vec![targets::cart_line("gid://shopify/CartLine/0")], percentage, "20% OFF"),
+            Some(schema::CartOperation::ProductDiscountsAdd(operation))
+                if operation.candidates[0].targets.len() == 1
+        ));
+    }
+
+    #[test]
+    fn merges_candidates_into_one_operation() {
+        let operations = vec![
+            order_percentage_discount(Percentage::whole(10), "10% OFF ORDER"),
+            product_percentage_discount(
+                vec![targets::cart_line("gid://shopify/CartLine/0")],
+                Percentage::whole(20),
+                "20% OFF PRODUCT",
+            )
+            .unwrap(),
+            order_percentage_discount(Percentage::whole(15), "15% OFF ORDER"),
+        ];
+
+        let operations =
+            merge_order_discounts(operations, schema::OrderDiscountSelectionStrategy::Maximum);
+
+        assert_eq!(operations.len(), 2);
+        assert!(matches!(
+            &operations[0],
+            schema::CartOperation::OrderDiscountsAdd(operation)
+                if operation.selection_strategy == schema::OrderDiscountSelectionStrategy::Maximum
+                    && operation.candidates.len() == 2
+                    && operation.candidates[1].message.as_deref() == Some("15% OFF ORDER")
+        ));
+        assert!(matches!(
+            &operations[1],
+            schema::CartOperation::ProductDiscountsAdd(_)
+        ));
+    }
+}
@@ -0,0 +1,32 @@
+//! The targets of discount candidates.
+
+use crate::schema;
+
+/// A whole cart line.
+pub fn cart_line(id: impl Into<String>) -> schema::ProductDiscountCandidateTarget {
+    schema::ProductDiscountCandidateTarget::CartLine(schema::CartLineTarget {
+        id: id.into(),
+        quantity: None,
+    })
+}
+
+/// The order subtotal, without excluding any cart line.
+pub fn order_subtotal() -> schema::OrderDiscountCandidateTarget {
+    schema::OrderDiscountCandidateTarget::OrderSubtotal(schema::OrderSubtotalTarget {
+        excluded_cart_line_ids: vec![],
+    })
+}
+
+/// All the delivery options of a delivery group.
+pub fn delivery_group(id: impl Into<String>) -> schema::DeliveryDiscountCandidateTarget {
+    schema::DeliveryDiscountCandidateTarget::DeliveryGroup(schema::DeliveryGroupTarget {
+        id: id.into(),
+    })
+}
+
+/// A single delivery option, by its handle.
+pub fn delivery_option(handle: impl Into<String>) -> schema::DeliveryDiscountCandidateTarget {
+    schema::DeliveryDiscountCandidateTarget::DeliveryOption(schema::DeliveryOptionTarget {
+        handle: handle.into(),
+    })
+}
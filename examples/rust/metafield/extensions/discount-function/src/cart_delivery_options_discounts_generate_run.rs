@@ -1,67 +1,138 @@
-use super::schema;
+use super::configuration::DiscountConfiguration;
+use super::schema::cart_delivery_options_discounts_generate_run as query;
+use super::strategy::DELIVERY_TARGET;
+use discount_core::explain::explain;
+use discount_core::{schema, DiscountClasses, DiscountError, ErrorKind};
 use shopify_function::prelude::*;
-use shopify_function::Result;
-
-#[derive(Deserialize)]
-#[shopify_function(rename_all = "camelCase")]
-pub struct DiscountConfiguration {
-    delivery_percentage: f64,
-}
 
 // [START discount-function.run.delivery]
 #[shopify_function]
-fn cart_delivery_options_discounts_generate_run(
-    input: schema::cart_delivery_options_discounts_generate_run::Input,
-) -> Result<schema::CartDeliveryOptionsDiscountsGenerateRunResult> {
-    let first_delivery_group = input
-        .cart()
-        .delivery_groups()
-        .first()
-        .ok_or("No delivery groups found")?;
-
+pub(crate) fn cart_delivery_options_discounts_generate_run(
+    input: query::Input,
+) -> shopify_function::Result<schema::CartDeliveryOptionsDiscountsGenerateRunResult> {
     // [START discount-function.run.delivery.parse-metafield]
-    let discount_configuration = match input.discount().metafield() {
-        Some(metafield) => metafield.json_value(),
-        None => return Err("No metafield provided".into()),
+    let metafield = input
+        .discount()
+        .metafield()
+        .map(|metafield| metafield.json_value());
+    let Some(discount_configuration) = DiscountConfiguration::read(metafield, DELIVERY_TARGET)?
+    else {
+        return Ok(schema::CartDeliveryOptionsDiscountsGenerateRunResult::empty());
     };
     // [END discount-function.run.delivery.parse-metafield]
     // [START discount-function.run.delivery.add-operations]
 
-    let has_shipping_discount_class = input
-        .discount()
-        .discount_classes()
-        .contains(&schema::DiscountClass::Shipping);
+    let discount_classes = DiscountClasses::new(input.discount().discount_classes());
+    explain!("discount classes: {discount_classes}");
 
-    if !has_shipping_discount_class {
-        return Ok(schema::CartDeliveryOptionsDiscountsGenerateRunResult { operations: vec![] });
+    if !discount_classes.shipping {
+        explain!("no SHIPPING discount class, no operations");
+        return Ok(schema::CartDeliveryOptionsDiscountsGenerateRunResult::empty());
     }
 
-    let mut operations = vec![];
+    if input.cart().delivery_groups().is_empty() {
+        let error = DiscountError::new(DELIVERY_TARGET, ErrorKind::NoDeliveryGroups);
+        explain!("{}", error.details());
+        return error.handle(schema::CartDeliveryOptionsDiscountsGenerateRunResult::empty());
+    }
 
-    // Only add delivery discount if both the class is allowed and percentage is set
-    if discount_configuration.delivery_percentage > 0.0 {
-        operations.push(schema::DeliveryOperation::DeliveryDiscountsAdd(
-            schema::DeliveryDiscountsAddOperation {
-                selection_strategy: schema::DeliveryDiscountSelectionStrategy::All,
-                candidates: vec![schema::DeliveryDiscountCandidate {
-                    targets: vec![schema::DeliveryDiscountCandidateTarget::DeliveryGroup(
-                        schema::DeliveryGroupTarget {
-                            id: first_delivery_group.id().clone(),
-                        },
-                    )],
-                    value: schema::DeliveryDiscountCandidateValue::Percentage(schema::Percentage {
-                        value: Decimal(discount_configuration.delivery_percentage),
-                    }),
-                    message: Some(format!(
-                        "{}% OFF DELIVERY",
-                        discount_configuration.delivery_percentage
-                    )),
-                    associated_discount_code: None,
-                }],
-            },
+    // Each strategy adds its operations, in the order of the configuration
+    let strategies = discount_configuration.strategies();
+    let names: Vec<String> = strategies.iter().map(ToString::to_string).collect();
+    explain!("strategies: {}", names.join(", "));
+    let mut operations = vec![];
+    for strategy in strategies {
+        operations.extend(strategy.implementation().delivery_operations(
+            &input,
+            &discount_configuration,
+            discount_classes,
         ));
     }
     // [END discount-function.run.delivery.add-operations]
     Ok(schema::CartDeliveryOptionsDiscountsGenerateRunResult { operations })
 }
+
 // [END discount-function.run.delivery]
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use discount_function_devtools::cart_builder::CartBuilder;
+    use discount_function_devtools::fixtures::run_fixtures;
+    use discount_function_devtools::properties::{
+        check_run_invariants, configured_percentage, delivery_groups, discount_classes,
+    };
+    use discount_function_devtools::run_to_json;
+    use proptest::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    fn runs_fixtures() {
+        run_fixtures(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/cart_delivery_options_discounts_generate_run"
+            ),
+            cart_delivery_options_discounts_generate_run,
+        );
+    }
+
+    #[test]
+    fn caps_shipping_rules_on_each_selected_delivery_option() {
+        let input = CartBuilder::new()
+            .delivery_group("gid://shopify/CartDeliveryGroup/0")
+            .delivery_option("standard", 5.0)
+            .delivery_group("gid://shopify/CartDeliveryGroup/1")
+            .delivery_option("express", 30.0)
+            .discount_classes(["SHIPPING"])
+            .metafield(json!({
+                "rules": [{
+                    "then": {
+                        "discountClass": "SHIPPING",
+                        "target": "DELIVERY_GROUPS",
+                        "percentage": 50,
+                        "maxAmount": 10
+                    }
+                }]
+            }))
+            .build();
+
+        let output = run_to_json(cart_delivery_options_discounts_generate_run, &input);
+
+        let candidates = &output["operations"][0]["deliveryDiscountsAdd"]["candidates"];
+        assert_eq!(
+            candidates[0]["value"],
+            json!({ "percentage": { "value": "50.0" } })
+        );
+        assert_eq!(
+            candidates[1]["value"],
+            json!({ "fixedAmount": { "amount": "10.0" } })
+        );
+        assert_eq!(
+            candidates[1]["targets"],
+            json!([{ "deliveryGroup": { "id": "gid://shopify/CartDeliveryGroup/1" } }])
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn satisfies_run_invariants(
+            delivery_groups in delivery_groups(),
+            discount_classes in discount_classes(),
+            delivery_percentage in configured_percentage(),
+        ) {
+            let input = json!({
+                "cart": { "deliveryGroups": delivery_groups },
+                "discount": {
+                    "discountClasses": discount_classes,
+                    "metafield": {
+                        "jsonValue": { "deliveryPercentage": delivery_percentage }
+                    }
+                }
+            });
+            let output = run_to_json(cart_delivery_options_discounts_generate_run, &input.to_string());
+
+            check_run_invariants(&input, &output)?;
+        }
+    }
+}
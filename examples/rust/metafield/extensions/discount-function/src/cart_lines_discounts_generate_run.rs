@@ -1,110 +1,544 @@
-use super::schema;
+use super::configuration::DiscountConfiguration;
+use super::schema::cart_lines_discounts_generate_run as query;
+use super::strategy::CART_LINES_TARGET;
+use discount_core::explain::explain;
+use discount_core::{schema, DiscountClasses};
 use shopify_function::prelude::*;
-use shopify_function::Result;
-
-#[derive(Deserialize)]
-#[shopify_function(rename_all = "camelCase")]
-pub struct DiscountConfiguration {
-    cart_line_percentage: f64,
-    order_percentage: f64,
-    collection_ids: Vec<String>,
-}
 
 // [START discount-function.run.cart]
 #[shopify_function]
-fn cart_lines_discounts_generate_run(
-    input: schema::cart_lines_discounts_generate_run::Input,
-) -> Result<schema::CartLinesDiscountsGenerateRunResult> {
+pub(crate) fn cart_lines_discounts_generate_run(
+    input: query::Input,
+) -> shopify_function::Result<schema::CartLinesDiscountsGenerateRunResult> {
     // [START discount-function.run.cart.parse-metafield]
-    let discount_configuration = match input.discount().metafield() {
-        Some(metafield) => metafield.json_value(),
-        None => return Err("No metafield provided".into()),
+    let metafield = input
+        .discount()
+        .metafield()
+        .map(|metafield| metafield.json_value());
+    let Some(discount_configuration) = DiscountConfiguration::read(metafield, CART_LINES_TARGET)?
+    else {
+        return Ok(schema::CartLinesDiscountsGenerateRunResult::empty());
     };
 
     // [END discount-function.run.cart.parse-metafield]
     // [START discount-function.run.cart.add-operations]
-    let has_order_discount_class = input
-        .discount()
-        .discount_classes()
-        .contains(&schema::DiscountClass::Order);
-    let has_product_discount_class = input
-        .discount()
-        .discount_classes()
-        .contains(&schema::DiscountClass::Product);
+    let discount_classes = DiscountClasses::new(input.discount().discount_classes());
+    explain!("discount classes: {discount_classes}");
 
-    if !has_order_discount_class && !has_product_discount_class {
-        return Ok(schema::CartLinesDiscountsGenerateRunResult { operations: vec![] });
+    if !discount_classes.has_cart_lines_class() {
+        explain!("no PRODUCT or ORDER discount class, no operations");
+        return Ok(schema::CartLinesDiscountsGenerateRunResult::empty());
     }
 
+    // Each strategy adds its operations, in the order of the configuration
+    let strategies = discount_configuration.strategies();
+    let names: Vec<String> = strategies.iter().map(ToString::to_string).collect();
+    explain!("strategies: {}", names.join(", "));
     let mut operations = vec![];
+    for strategy in strategies {
+        operations.extend(strategy.implementation().cart_lines_operations(
+            &input,
+            &discount_configuration,
+            discount_classes,
+        ));
+    }
+    // Competing discounts become the candidates of a single operation
+    let operations = discount_configuration
+        .selection_strategies
+        .apply(operations);
+    // [END discount-function.run.cart.add-operations]
+    Ok(schema::CartLinesDiscountsGenerateRunResult { operations })
+}
 
-    // Add product discounts first if available and allowed
-    if has_product_discount_class && discount_configuration.cart_line_percentage > 0.0 {
-        let mut cart_line_targets = vec![];
-        for line in input.cart().lines() {
-            // [START discount-function.run.cart.product.in_any_collection]
-            if let schema::cart_lines_discounts_generate_run::input::cart::lines::Merchandise::ProductVariant(variant) = &line.merchandise() {
-                if *variant.product().in_any_collection()
-                    || discount_configuration.collection_ids.is_empty()
-                {
-                    cart_line_targets.push(schema::ProductDiscountCandidateTarget::CartLine(
-                        schema::CartLineTarget {
-                            id: line.id().clone(),
-                            quantity: None,
-                        },
-                    ));
-                }
-            }
-            // [END discount-function.run.cart.product.in_any_collection]
-        }
+// [END discount_function.run.cart]
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use discount_core::explain;
+    use discount_function_devtools::cart_builder::CartBuilder;
+    use discount_function_devtools::fixtures::run_fixtures;
+    use discount_function_devtools::properties::{
+        cart_lines, check_run_invariants, configured_percentage, discount_classes, targeted_ids,
+    };
+    use discount_function_devtools::run_to_json;
+    use proptest::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    fn runs_fixtures() {
+        run_fixtures(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/cart_lines_discounts_generate_run"
+            ),
+            cart_lines_discounts_generate_run,
+        );
+    }
+
+    #[test]
+    fn discounts_only_products_in_collections() {
+        let input = CartBuilder::new()
+            .line("gid://shopify/CartLine/0", 40.0)
+            .with_product_in_collection()
+            .line("gid://shopify/CartLine/1", 10.0)
+            .discount_classes(["PRODUCT"])
+            .metafield(json!({
+                "cartLinePercentage": 20,
+                "orderPercentage": 0,
+                "collectionIds": ["gid://shopify/Collection/1"]
+            }))
+            .build();
+
+        let output = run_to_json(cart_lines_discounts_generate_run, &input);
+
+        assert_eq!(
+            targeted_ids(&output, "cartLine"),
+            vec!["gid://shopify/CartLine/0"]
+        );
+    }
+
+    #[test]
+    fn applies_rules_in_order() {
+        let input = CartBuilder::new()
+            .line("gid://shopify/CartLine/0", 40.0)
+            .product_tag("sale")
+            .line("gid://shopify/CartLine/1", 10.0)
+            .product_tag("sale")
+            .line("gid://shopify/CartLine/2", 50.0)
+            .discount_classes(["PRODUCT", "ORDER"])
+            .metafield(json!({
+                "productTags": ["sale"],
+                "rules": [
+                    {
+                        "when": { "productTags": ["sale"] },
+                        "then": {
+                            "discountClass": "PRODUCT",
+                            "target": "MOST_EXPENSIVE_CART_LINE",
+                            "percentage": 25,
+                            "message": "25% OFF SALE"
+                        }
+                    },
+                    {
+                        "when": { "minSubtotal": 200 },
+                        "then": { "discountClass": "ORDER", "fixedAmount": 20 }
+                    },
+                    {
+                        "then": { "discountClass": "ORDER", "percentage": 50, "maxAmount": 15 }
+                    }
+                ]
+            }))
+            .build();
+
+        let output = run_to_json(cart_lines_discounts_generate_run, &input);
+
+        assert_eq!(
+            output["operations"][0]["productDiscountsAdd"]["candidates"],
+            json!([{
+                "associatedDiscountCode": null,
+                "message": "25% OFF SALE",
+                "targets": [{ "cartLine": { "id": "gid://shopify/CartLine/0", "quantity": null } }],
+                "value": { "percentage": { "value": "25.0" } }
+            }])
+        );
+        assert_eq!(
+            output["operations"][1]["orderDiscountsAdd"]["candidates"][0]["value"],
+            json!({ "fixedAmount": { "amount": "15.0" } })
+        );
+        assert_eq!(
+            output["operations"][1]["orderDiscountsAdd"]["candidates"][0]["message"],
+            "15 OFF ORDER"
+        );
+        assert_eq!(output["operations"].as_array().map(Vec::len), Some(2));
+    }
+
+    #[test]
+    fn rounds_fixed_amounts_to_the_currency() {
+        let input = CartBuilder::new()
+            .line("gid://shopify/CartLine/0", 1000.0)
+            .currency_code("JPY")
+            .discount_classes(["ORDER"])
+            .metafield(json!({
+                "rules": [
+                    { "then": { "discountClass": "ORDER", "fixedAmount": 99.5 } },
+                    { "then": { "discountClass": "ORDER", "percentage": 10, "maxAmount": 0.4 } }
+                ]
+            }))
+            .build();
+        explain::take_trace();
+
+        let output = run_to_json(cart_lines_discounts_generate_run, &input);
+
+        assert_eq!(
+            output["operations"][0]["orderDiscountsAdd"]["candidates"][0]["value"],
+            json!({ "fixedAmount": { "amount": "100.0" } })
+        );
+        assert_eq!(output["operations"].as_array().map(Vec::len), Some(1));
+        assert_eq!(
+            explain::take_trace()[3..],
+            [
+                "rule 1: 100 off the order subtotal",
+                "rule 2 skipped: INVALID_CONFIGURATION in cart_lines_discounts_generate_run at rules[1].then.maxAmount: 0.4 rounds to 0 JPY",
+            ]
+        );
+    }
+
+    #[test]
+    fn explains_rules_that_do_not_apply() {
+        let input = CartBuilder::new()
+            .line("gid://shopify/CartLine/0", 40.0)
+            .quantity(2)
+            .customer_tags(["vip"])
+            .date("2025-11-27")
+            .discount_classes(["PRODUCT", "ORDER"])
+            .metafield(json!({
+                "customerTags": ["vip", "wholesale"],
+                "rules": [
+                    {
+                        "when": { "customerTags": ["wholesale"] },
+                        "then": { "discountClass": "ORDER", "percentage": 10 }
+                    },
+                    {
+                        "when": { "startsOn": "2025-11-28", "endsOn": "2025-12-01" },
+                        "then": { "discountClass": "ORDER", "percentage": 30 }
+                    },
+                    {
+                        "when": { "minQuantity": 3 },
+                        "then": { "discountClass": "PRODUCT", "percentage": 10 }
+                    },
+                    {
+                        "then": { "discountClass": "ORDER", "target": "CART_LINES", "percentage": 10 }
+                    },
+                    {
+                        "when": { "customerTags": ["vip"], "maxSubtotal": 100 },
+                        "then": { "discountClass": "PRODUCT", "fixedAmount": 5 }
+                    }
+                ]
+            }))
+            .build();
+        explain::take_trace();
+
+        run_to_json(cart_lines_discounts_generate_run, &input);
+
+        assert_eq!(
+            explain::take_trace()[3..],
+            [
+                "rule 1 skipped: the customer has none of the tags \"wholesale\"",
+                "rule 2 skipped: the rule starts on 2025-11-28, it's 2025-11-27",
+                "rule 3 skipped: no cart line matches the rule",
+                "rule 4 skipped: INVALID_CONFIGURATION in cart_lines_discounts_generate_run at rules[3].then.target: CartLines isn't a target of ORDER discounts",
+                "rule 5: 5 off 1 cart lines",
+            ]
+        );
+    }
+
+    #[test]
+    fn explains_skipped_discounts() {
+        let input = CartBuilder::new()
+            .line("gid://shopify/CartLine/0", 40.0)
+            .with_product_in_collection()
+            .line("gid://shopify/CartLine/1", 10.0)
+            .discount_classes(["PRODUCT"])
+            .metafield(json!({
+                "cartLinePercentage": 20,
+                "orderPercentage": 10,
+                "collectionIds": ["gid://shopify/Collection/1"]
+            }))
+            .build();
+        explain::take_trace();
+
+        run_to_json(cart_lines_discounts_generate_run, &input);
+
+        assert_eq!(
+            explain::take_trace(),
+            vec![
+                "metafield parsed: 3 rules, migrated from version 1",
+                "discount classes: PRODUCT",
+                "strategies: RULES",
+                "rule 1: 20% off 1 cart lines",
+                "rule 2 skipped: no ORDER discount class",
+            ]
+        );
+    }
 
-        if !cart_line_targets.is_empty() {
-            operations.push(schema::CartOperation::ProductDiscountsAdd(
-                schema::ProductDiscountsAddOperation {
-                    selection_strategy: schema::ProductDiscountSelectionStrategy::First,
-                    candidates: vec![schema::ProductDiscountCandidate {
-                        targets: cart_line_targets,
-                        message: Some(format!(
-                            "{}% OFF PRODUCT",
-                            discount_configuration.cart_line_percentage
-                        )),
-                        value: schema::ProductDiscountCandidateValue::Percentage(
-                            schema::Percentage {
-                                value: Decimal(discount_configuration.cart_line_percentage),
-                            },
-                        ),
-                        associated_discount_code: None,
-                    }],
-                },
-            ));
+    #[test]
+    fn applies_strategies_in_configured_order() {
+        let input = CartBuilder::new()
+            .line("gid://shopify/CartLine/0", 120.0)
+            .discount_classes(["PRODUCT", "ORDER"])
+            .metafield(json!({
+                "strategies": ["SUBTOTAL_TIERS", "RULES"],
+                "subtotalTiers": [{ "minSubtotal": 100, "percentage": 5 }],
+                "rules": [{ "then": { "discountClass": "PRODUCT", "percentage": 10 } }]
+            }))
+            .build();
+        explain::take_trace();
+
+        let output = run_to_json(cart_lines_discounts_generate_run, &input);
+
+        assert_eq!(
+            output["operations"][0]["orderDiscountsAdd"]["candidates"][0]["message"],
+            "5% OFF ORDER"
+        );
+        assert_eq!(
+            output["operations"][1]["productDiscountsAdd"]["candidates"][0]["message"],
+            "10% OFF PRODUCT"
+        );
+        assert_eq!(
+            explain::take_trace()[2..],
+            [
+                "strategies: SUBTOTAL_TIERS, RULES",
+                "subtotal tiers: 5% off the order subtotal, from 100",
+                "rule 1: 10% off 1 cart lines",
+            ]
+        );
+    }
+
+    #[test]
+    fn migrates_older_versions_of_the_metafield() {
+        let input = |metafield| {
+            CartBuilder::new()
+                .line("gid://shopify/CartLine/0", 40.0)
+                .discount_classes(["ORDER"])
+                .metafield(metafield)
+                .build()
+        };
+
+        let output = run_to_json(
+            cart_lines_discounts_generate_run,
+            &input(json!({ "version": 1, "orderPercentage": 10 })),
+        );
+        assert_eq!(
+            output["operations"][0]["orderDiscountsAdd"]["candidates"][0]["message"],
+            "10% OFF ORDER"
+        );
+
+        // Version 2 only reads rules
+        let output = run_to_json(
+            cart_lines_discounts_generate_run,
+            &input(json!({ "version": 2, "orderPercentage": 10 })),
+        );
+        assert_eq!(output, json!({ "operations": [] }));
+
+        // A newer version is read as the current one, whatever the fallback
+        for fallback in ["IGNORE_FIELD", "USE_DEFAULTS", "NO_OP"] {
+            explain::take_trace();
+            let output = run_to_json(
+                cart_lines_discounts_generate_run,
+                &input(json!({
+                    "version": 3,
+                    "fallback": fallback,
+                    "rules": [{ "then": { "discountClass": "ORDER", "percentage": 5 } }]
+                })),
+            );
+            assert_eq!(
+                output["operations"][0]["orderDiscountsAdd"]["candidates"][0]["message"],
+                "5% OFF ORDER"
+            );
+            let trace = explain::take_trace();
+            assert_eq!(
+                trace[0],
+                "metafield parsed: 1 rules, version 3 read as version 2"
+            );
+            assert!(!trace
+                .iter()
+                .any(|decision| decision.starts_with("INVALID_CONFIGURATION")));
         }
+
+        explain::take_trace();
+        let output = run_to_json(
+            cart_lines_discounts_generate_run,
+            &input(json!({ "version": 0, "orderPercentage": 10 })),
+        );
+        assert_eq!(
+            output["operations"][0]["orderDiscountsAdd"]["candidates"][0]["message"],
+            "10% OFF ORDER"
+        );
+        assert_eq!(
+            explain::take_trace()[1],
+            "INVALID_CONFIGURATION in cart_lines_discounts_generate_run at version: invalid value, the field is ignored"
+        );
     }
 
-    // Then add order discounts if available and allowed
-    if has_order_discount_class && discount_configuration.order_percentage > 0.0 {
-        operations.push(schema::CartOperation::OrderDiscountsAdd(
-            schema::OrderDiscountsAddOperation {
-                selection_strategy: schema::OrderDiscountSelectionStrategy::First,
-                candidates: vec![schema::OrderDiscountCandidate {
-                    targets: vec![schema::OrderDiscountCandidateTarget::OrderSubtotal(
-                        schema::OrderSubtotalTarget {
-                            excluded_cart_line_ids: vec![],
-                        },
-                    )],
-                    message: Some(format!(
-                        "{}% OFF ORDER",
-                        discount_configuration.order_percentage
-                    )),
-                    value: schema::OrderDiscountCandidateValue::Percentage(schema::Percentage {
-                        value: Decimal(discount_configuration.order_percentage),
-                    }),
-                    conditions: None,
-                    associated_discount_code: None,
-                }],
-            },
-        ));
+    #[test]
+    fn merges_competing_discounts_with_the_selection_strategy() {
+        let input = CartBuilder::new()
+            .line("gid://shopify/CartLine/0", 120.0)
+            .discount_classes(["PRODUCT", "ORDER"])
+            .metafield(json!({
+                "strategies": ["SUBTOTAL_TIERS", "RULES"],
+                "subtotalTiers": [{ "minSubtotal": 100, "percentage": 5 }],
+                "selectionStrategies": { "product": "ALL", "order": "MAXIMUM" },
+                "rules": [
+                    { "then": { "discountClass": "ORDER", "fixedAmount": 10 } },
+                    { "then": { "discountClass": "PRODUCT", "percentage": 10 } },
+                    { "then": { "discountClass": "PRODUCT", "percentage": 20 } }
+                ]
+            }))
+            .build();
+        explain::take_trace();
+
+        let output = run_to_json(cart_lines_discounts_generate_run, &input);
+
+        let order_discounts = &output["operations"][0]["orderDiscountsAdd"];
+        assert_eq!(order_discounts["selectionStrategy"], "MAXIMUM");
+        assert_eq!(order_discounts["candidates"][0]["message"], "5% OFF ORDER");
+        assert_eq!(order_discounts["candidates"][1]["message"], "10 OFF ORDER");
+        let product_discounts = &output["operations"][1]["productDiscountsAdd"];
+        assert_eq!(product_discounts["selectionStrategy"], "ALL");
+        assert_eq!(
+            product_discounts["candidates"][0]["message"],
+            "10% OFF PRODUCT"
+        );
+        assert_eq!(
+            product_discounts["candidates"][1]["message"],
+            "20% OFF PRODUCT"
+        );
+        assert_eq!(output["operations"].as_array().map(Vec::len), Some(2));
+        assert_eq!(
+            explain::take_trace().last().map(String::as_str),
+            Some("selection strategy of order discounts: MAXIMUM")
+        );
+    }
+
+    #[test]
+    fn keeps_order_discounts_apart_with_the_all_selection_strategy() {
+        let input = CartBuilder::new()
+            .line("gid://shopify/CartLine/0", 120.0)
+            .discount_classes(["ORDER"])
+            .metafield(json!({
+                "selectionStrategies": { "order": "ALL" },
+                "rules": [
+                    { "then": { "discountClass": "ORDER", "fixedAmount": 10 } },
+                    { "then": { "discountClass": "ORDER", "percentage": 5 } }
+                ]
+            }))
+            .build();
+
+        let output = run_to_json(cart_lines_discounts_generate_run, &input);
+
+        // Order discount operations have no ALL selection strategy
+        assert_eq!(output["operations"].as_array().map(Vec::len), Some(2));
+        assert_eq!(
+            output["operations"][1]["orderDiscountsAdd"]["candidates"][0]["message"],
+            "5% OFF ORDER"
+        );
+    }
+
+    #[test]
+    fn applies_no_discounts_without_a_metafield() {
+        let input = CartBuilder::new()
+            .line("gid://shopify/CartLine/0", 40.0)
+            .discount_classes(["PRODUCT", "ORDER"])
+            .build();
+        explain::take_trace();
+
+        let output = run_to_json(cart_lines_discounts_generate_run, &input);
+
+        assert_eq!(output, json!({ "operations": [] }));
+        assert_eq!(
+            explain::take_trace()[0],
+            "MISSING_METAFIELD in cart_lines_discounts_generate_run at discount.metafield: No metafield provided, applying no discounts"
+        );
+    }
+
+    #[test]
+    fn ignores_invalid_and_unknown_fields() {
+        let input = CartBuilder::new()
+            .line("gid://shopify/CartLine/0", 40.0)
+            .discount_classes(["PRODUCT", "ORDER"])
+            .metafield(json!({
+                "cartLinePercentage": "twenty",
+                "orderPercentage": 10,
+                "addedInALaterVersion": true
+            }))
+            .build();
+        explain::take_trace();
+
+        let output = run_to_json(cart_lines_discounts_generate_run, &input);
+
+        assert_eq!(
+            output["operations"][0]["orderDiscountsAdd"]["candidates"][0]["message"],
+            "10% OFF ORDER"
+        );
+        assert_eq!(output["operations"].as_array().map(Vec::len), Some(1));
+        assert_eq!(
+            explain::take_trace()[1],
+            "INVALID_CONFIGURATION in cart_lines_discounts_generate_run at cartLinePercentage: invalid value, the field is ignored"
+        );
+    }
+
+    #[test]
+    fn applies_the_configured_fallback() {
+        let metafield = |fallback: &str| {
+            json!({
+                "fallback": fallback,
+                "orderPercentage": 15,
+                "rules": [{ "then": { "discountClass": "BOGUS" } }]
+            })
+        };
+        let input = |fallback| {
+            CartBuilder::new()
+                .line("gid://shopify/CartLine/0", 40.0)
+                .discount_classes(["ORDER"])
+                .metafield(metafield(fallback))
+                .build()
+        };
+
+        let output = run_to_json(cart_lines_discounts_generate_run, &input("IGNORE_FIELD"));
+        assert_eq!(
+            output["operations"][0]["orderDiscountsAdd"]["candidates"][0]["message"],
+            "15% OFF ORDER"
+        );
+
+        // The default discounts replace the whole configuration
+        let output = run_to_json(cart_lines_discounts_generate_run, &input("USE_DEFAULTS"));
+        assert_eq!(
+            output["operations"][0]["orderDiscountsAdd"]["candidates"][0]["message"],
+            "10% OFF ORDER"
+        );
+        let use_defaults = output;
+
+        explain::take_trace();
+        let output = run_to_json(cart_lines_discounts_generate_run, &input("NO_OP"));
+        assert_eq!(output, json!({ "operations": [] }));
+        assert_ne!(output, use_defaults);
+        assert_eq!(
+            explain::take_trace()[1],
+            "INVALID_CONFIGURATION in cart_lines_discounts_generate_run at rules: invalid value, no discounts apply"
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn satisfies_run_invariants(
+            lines in cart_lines(),
+            discount_classes in discount_classes(),
+            cart_line_percentage in configured_percentage(),
+            order_percentage in configured_percentage(),
+            has_collection_ids in any::<bool>(),
+        ) {
+            let collection_ids: &[&str] = if has_collection_ids {
+                &["gid://shopify/Collection/1"]
+            } else {
+                &[]
+            };
+            let input = json!({
+                "cart": { "lines": lines },
+                "discount": {
+                    "discountClasses": discount_classes,
+                    "metafield": {
+                        "jsonValue": {
+                            "cartLinePercentage": cart_line_percentage,
+                            "orderPercentage": order_percentage,
+                            "collectionIds": collection_ids
+                        }
+                    }
+                }
+            });
+            let output = run_to_json(cart_lines_discounts_generate_run, &input.to_string());
+
+            check_run_invariants(&input, &output)?;
+        }
     }
-    // [END discount-function.run.cart.add-operations]
-    Ok(schema::CartLinesDiscountsGenerateRunResult { operations })
 }
-// [END discount_function.run.cart]
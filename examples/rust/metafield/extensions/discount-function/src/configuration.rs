@@ -0,0 +1,254 @@
+// [START discount-function.configuration]
+//! The configuration stored in the discount's metafield.
+//!
+//! `strategies` selects the discount strategies applied by the run targets,
+//! and their order. A metafield without `strategies` only applies its rules:
+//!
+//! ```json
+//! {
+//!   "strategies": ["SUBTOTAL_TIERS", "RULES"],
+//!   "subtotalTiers": [
+//!     { "minSubtotal": 100, "percentage": 5 },
+//!     { "minSubtotal": 200, "percentage": 10 }
+//!   ],
+//!   "rules": []
+//! }
+//! ```
+//!
+//! `version` is the version of the configuration's shape, which is
+//! [`CURRENT_VERSION`] for metafields saved by the current settings UI. Older
+//! metafields are upgraded to the current shape when they're read, by the
+//! migrations in [`super::migrations`], so discounts keep working while their
+//! metafields are rewritten. A metafield without `version` is version 1, and
+//! a newer version than the function knows is read as the current one.
+//!
+//! A discount without a metafield, such as one created before its settings
+//! were saved, applies no discounts. Fields the configuration doesn't know are
+//! ignored, so older versions of the function keep reading newer metafields.
+//! Fields with an invalid value are handled as `fallback` says: `IGNORE_FIELD`
+//! (the default) reads them as if they weren't set, `USE_DEFAULTS` applies the
+//! [`DiscountConfiguration::defaults`] instead, and `NO_OP` applies no
+//! discounts.
+
+use super::migrations::MIGRATIONS;
+use super::rules::{DiscountClass, Rule, RuleTarget};
+use super::selection::SelectionStrategies;
+use super::strategy::Strategy;
+use super::subtotal_tiers::SubtotalTier;
+use discount_core::explain::explain;
+use discount_core::money::Percentage;
+use discount_core::{DiscountError, ErrorKind};
+use shopify_function::wasm_api::{read::Error, Deserialize, Value};
+use std::borrow::Cow;
+use std::fmt;
+
+/// The version of the configuration's shape read by the run targets.
+pub const CURRENT_VERSION: u32 = 2;
+
+/// The configuration stored in the discount's metafield, read by both run
+/// targets, in the shape of [`CURRENT_VERSION`].
+#[derive(Clone, Default)]
+pub struct DiscountConfiguration {
+    strategies: Option<Vec<Strategy>>,
+    pub rules: Vec<Rule>,
+    pub subtotal_tiers: Vec<SubtotalTier>,
+    pub selection_strategies: SelectionStrategies,
+    fallback: Fallback,
+    /// The version of the metafield, when it was migrated from an older one.
+    migrated_from: Option<u32>,
+    /// The version of the metafield, when it's newer than the current one.
+    newer_version: Option<u32>,
+    /// The fields whose value is invalid, which were read as if they weren't
+    /// set.
+    invalid_fields: Vec<String>,
+}
+
+/// What the run targets do when fields of the configuration are invalid.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Fallback {
+    #[default]
+    IgnoreField,
+    UseDefaults,
+    NoOp,
+}
+
+impl DiscountConfiguration {
+    /// The configuration of a run target's metafield, or `None` when the target
+    /// applies no operations.
+    pub fn read<'a>(
+        metafield: Option<&'a Self>,
+        target: &'static str,
+    ) -> shopify_function::Result<Option<Cow<'a, Self>>> {
+        let Some(configuration) = metafield else {
+            let error = DiscountError::new(target, ErrorKind::MissingMetafield);
+            explain!("{}, applying no discounts", error.details());
+            return error.handle(Some(Cow::Owned(Self::default())));
+        };
+        explain!("metafield parsed: {configuration}");
+
+        if configuration.invalid_fields.is_empty() {
+            return Ok(Some(Cow::Borrowed(configuration)));
+        }
+        for field in &configuration.invalid_fields {
+            let error = DiscountError::new(
+                target,
+                ErrorKind::InvalidConfiguration {
+                    field: field.clone(),
+                    reason: format!("invalid value, {}", configuration.fallback),
+                },
+            );
+            explain!("{}", error.details());
+        }
+        match configuration.fallback {
+            Fallback::IgnoreField => Ok(Some(Cow::Borrowed(configuration))),
+            Fallback::UseDefaults => Ok(Some(Cow::Owned(Self::defaults()))),
+            Fallback::NoOp => Ok(None),
+        }
+    }
+
+    /// The configuration applied by the `USE_DEFAULTS` fallback: the discounts
+    /// of the default example, which are 10% off the order subtotal, 20% off
+    /// the most expensive cart line and free delivery for the first delivery
+    /// group.
+    pub fn defaults() -> Self {
+        let mut most_expensive_line = Rule::percentage(
+            DiscountClass::Product,
+            Percentage::whole(20),
+            &[],
+            "PRODUCT",
+        );
+        most_expensive_line.then.target = Some(RuleTarget::MostExpensiveCartLine);
+        Self {
+            rules: vec![
+                Rule::percentage(DiscountClass::Order, Percentage::whole(10), &[], "ORDER"),
+                most_expensive_line,
+                Rule::percentage(
+                    DiscountClass::Shipping,
+                    Percentage::HUNDRED,
+                    &[],
+                    "DELIVERY",
+                ),
+            ],
+            ..Self::default()
+        }
+    }
+
+    /// The fields of the metafield with an invalid value.
+    pub fn invalid_fields(&self) -> &[String] {
+        &self.invalid_fields
+    }
+
+    pub fn fallback(&self) -> Fallback {
+        self.fallback
+    }
+
+    /// The strategies of the discount, in the order they apply.
+    pub fn strategies(&self) -> &[Strategy] {
+        self.strategies.as_deref().unwrap_or(&[Strategy::Rules])
+    }
+}
+
+impl Deserialize for DiscountConfiguration {
+    fn deserialize(value: &Value) -> Result<Self, Error> {
+        let mut configuration = Self::default();
+        if !value.is_obj() {
+            configuration.invalid_fields.push("jsonValue".to_string());
+            return Ok(configuration);
+        }
+
+        let mut invalid_fields = vec![];
+        let version = match field(value, "version", &mut invalid_fields) {
+            Some(0) => {
+                invalid_fields.push("version".to_string());
+                1
+            }
+            Some(version @ 1..=CURRENT_VERSION) => version,
+            // A newer shape is read as the current one, as far as it can be
+            Some(version) => {
+                configuration.newer_version = Some(version);
+                CURRENT_VERSION
+            }
+            None => 1,
+        };
+        configuration.strategies = field(value, "strategies", &mut invalid_fields);
+        configuration.rules = field(value, "rules", &mut invalid_fields).unwrap_or_default();
+        configuration.subtotal_tiers =
+            field(value, "subtotalTiers", &mut invalid_fields).unwrap_or_default();
+        configuration.selection_strategies =
+            field(value, "selectionStrategies", &mut invalid_fields).unwrap_or_default();
+        configuration.fallback = field(value, "fallback", &mut invalid_fields).unwrap_or_default();
+
+        // Each migration upgrades the configuration from its version to the next
+        for migrate in &MIGRATIONS[version as usize - 1..] {
+            migrate(value, &mut configuration, &mut invalid_fields);
+        }
+        if version < CURRENT_VERSION {
+            configuration.migrated_from = Some(version);
+        }
+        configuration.invalid_fields = invalid_fields;
+        Ok(configuration)
+    }
+}
+
+/// A field of the metafield, or `None` when it isn't set. A field with an
+/// invalid value is added to `invalid_fields` and read as if it weren't set.
+pub(crate) fn field<T: Deserialize>(
+    value: &Value,
+    name: &str,
+    invalid_fields: &mut Vec<String>,
+) -> Option<T> {
+    Option::deserialize(&value.get_obj_prop(name)).unwrap_or_else(|_| {
+        invalid_fields.push(name.to_string());
+        None
+    })
+}
+
+impl Fallback {
+    pub const ALL: [Self; 3] = [Self::IgnoreField, Self::UseDefaults, Self::NoOp];
+
+    /// The name of the fallback in the metafield.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::IgnoreField => "IGNORE_FIELD",
+            Self::UseDefaults => "USE_DEFAULTS",
+            Self::NoOp => "NO_OP",
+        }
+    }
+}
+
+impl Deserialize for Fallback {
+    fn deserialize(value: &Value) -> Result<Self, Error> {
+        let name: String = Deserialize::deserialize(value)?;
+        Self::ALL
+            .into_iter()
+            .find(|fallback| fallback.name() == name)
+            .ok_or(Error::InvalidType)
+    }
+}
+
+impl fmt::Display for Fallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::IgnoreField => "the field is ignored",
+            Self::UseDefaults => "the default discounts apply",
+            Self::NoOp => "no discounts apply",
+        })
+    }
+}
+
+impl fmt::Display for DiscountConfiguration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} rules", self.rules.len())?;
+        if !self.subtotal_tiers.is_empty() {
+            write!(f, ", {} subtotal tiers", self.subtotal_tiers.len())?;
+        }
+        if let Some(version) = self.migrated_from {
+            write!(f, ", migrated from version {version}")?;
+        }
+        if let Some(version) = self.newer_version {
+            write!(f, ", version {version} read as version {CURRENT_VERSION}")?;
+        }
+        Ok(())
+    }
+}
+// [END discount-function.configuration]
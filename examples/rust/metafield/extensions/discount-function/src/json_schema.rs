@@ -0,0 +1,232 @@
+//! The JSON Schema of the configuration metafield.
+//!
+//! The settings UI and the admin validate a configuration against the schema
+//! before saving it with `metafieldsSet`, so a misspelled field or a value of
+//! the wrong type is rejected there instead of being ignored at checkout. The
+//! schema is generated from the configuration types, and checked in at
+//! [`PATH`]. A test fails when the checked-in schema is out of date, and
+//! `UPDATE_SNAPSHOTS=1 cargo test` rewrites it. `cargo run -- json-schema`
+//! prints it.
+
+use super::configuration::{DiscountConfiguration, Fallback, CURRENT_VERSION};
+use super::rules::{Action, Conditions, DiscountClass, Rule, RuleTarget};
+use super::selection::{SelectionStrategies, SelectionStrategy};
+use super::strategy::Strategy;
+use super::subtotal_tiers::SubtotalTier;
+use discount_core::money::{Money, Percentage};
+use serde_json::{json, Value};
+
+/// The path of the checked-in schema, from the function's directory.
+pub const PATH: &str = "configuration.schema.json";
+
+/// A type of the configuration, described as a JSON Schema.
+pub trait JsonSchema {
+    fn json_schema() -> Value;
+}
+
+/// The schema of the whole metafield.
+pub fn configuration_schema() -> Value {
+    let mut schema = DiscountConfiguration::json_schema();
+    schema["$schema"] = json!("https://json-schema.org/draft/2020-12/schema");
+    schema["title"] = json!("Discount function configuration");
+    schema
+}
+
+impl JsonSchema for DiscountConfiguration {
+    fn json_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "version": {
+                    "description": "The version of the configuration's shape. Defaults to 1.",
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": CURRENT_VERSION
+                },
+                "strategies": {
+                    "description": "The strategies applied, in order. Defaults to [\"RULES\"].",
+                    "type": "array",
+                    "items": Strategy::json_schema()
+                },
+                "rules": array::<Rule>(),
+                "subtotalTiers": array::<SubtotalTier>(),
+                "selectionStrategies": SelectionStrategies::json_schema(),
+                "fallback": Fallback::json_schema(),
+                "cartLinePercentage": version_1(Percentage::json_schema()),
+                "orderPercentage": version_1(Percentage::json_schema()),
+                "deliveryPercentage": version_1(Percentage::json_schema()),
+                "collectionIds": {
+                    "description": "Every collection used by a rule, passed to the input queries.",
+                    "type": "array",
+                    "items": { "type": "string" }
+                },
+                "productTags": {
+                    "description": "Every product tag used by a rule, passed to the input queries.",
+                    "type": "array",
+                    "items": { "type": "string" }
+                },
+                "customerTags": {
+                    "description": "Every customer tag used by a rule, passed to the input queries.",
+                    "type": "array",
+                    "items": { "type": "string" }
+                }
+            },
+            "additionalProperties": false
+        })
+    }
+}
+
+impl JsonSchema for Rule {
+    fn json_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "when": Conditions::json_schema(),
+                "then": Action::json_schema()
+            },
+            "required": ["then"],
+            "additionalProperties": false
+        })
+    }
+}
+
+impl JsonSchema for Conditions {
+    fn json_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "collectionIds": { "type": "array", "items": { "type": "string" } },
+                "productTags": { "type": "array", "items": { "type": "string" } },
+                "minQuantity": { "type": "integer", "minimum": 1 },
+                "minSubtotal": Money::json_schema(),
+                "maxSubtotal": Money::json_schema(),
+                "customerTags": { "type": "array", "items": { "type": "string" } },
+                "startsOn": { "type": "string", "format": "date" },
+                "endsOn": { "type": "string", "format": "date" }
+            },
+            "additionalProperties": false
+        })
+    }
+}
+
+impl JsonSchema for Action {
+    fn json_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "discountClass": DiscountClass::json_schema(),
+                "target": RuleTarget::json_schema(),
+                "percentage": Percentage::json_schema(),
+                "fixedAmount": Money::json_schema(),
+                "maxAmount": Money::json_schema(),
+                "message": { "type": "string" }
+            },
+            "required": ["discountClass"],
+            // Either a percentage, capped at `maxAmount`, or a fixed amount
+            "oneOf": [{ "required": ["percentage"] }, { "required": ["fixedAmount"] }],
+            "dependentRequired": { "maxAmount": ["percentage"] },
+            "additionalProperties": false
+        })
+    }
+}
+
+impl JsonSchema for SubtotalTier {
+    fn json_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "minSubtotal": Money::json_schema(),
+                "percentage": Percentage::json_schema(),
+                "message": { "type": "string" }
+            },
+            "required": ["minSubtotal", "percentage"],
+            "additionalProperties": false
+        })
+    }
+}
+
+impl JsonSchema for SelectionStrategies {
+    fn json_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "product": SelectionStrategy::json_schema(),
+                "order": SelectionStrategy::json_schema()
+            },
+            "additionalProperties": false
+        })
+    }
+}
+
+impl JsonSchema for SelectionStrategy {
+    fn json_schema() -> Value {
+        names(Self::ALL.map(Self::name))
+    }
+}
+
+impl JsonSchema for Strategy {
+    fn json_schema() -> Value {
+        names(Self::ALL.map(Self::name))
+    }
+}
+
+impl JsonSchema for DiscountClass {
+    fn json_schema() -> Value {
+        names(Self::ALL.map(Self::name))
+    }
+}
+
+impl JsonSchema for RuleTarget {
+    fn json_schema() -> Value {
+        names(Self::ALL.map(Self::name))
+    }
+}
+
+impl JsonSchema for Fallback {
+    fn json_schema() -> Value {
+        names(Self::ALL.map(Self::name))
+    }
+}
+
+impl JsonSchema for Money {
+    fn json_schema() -> Value {
+        json!({ "type": "number", "minimum": 0 })
+    }
+}
+
+impl JsonSchema for Percentage {
+    fn json_schema() -> Value {
+        json!({ "type": "number", "minimum": 0, "maximum": 100 })
+    }
+}
+
+fn array<T: JsonSchema>() -> Value {
+    json!({ "type": "array", "items": T::json_schema() })
+}
+
+fn names(names: impl IntoIterator<Item = &'static str>) -> Value {
+    let names: Vec<_> = names.into_iter().collect();
+    json!({ "type": "string", "enum": names })
+}
+
+/// A field only read from version 1 metafields without `rules`.
+fn version_1(mut schema: Value) -> Value {
+    schema["description"] = json!("Version 1 only, when there are no rules.");
+    schema
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use discount_function_devtools::snapshots::check_snapshot;
+    use std::path::Path;
+
+    #[test]
+    fn matches_the_checked_in_schema() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(PATH);
+
+        if let Err(failure) = check_snapshot(&path, &configuration_schema()) {
+            panic!("{failure}");
+        }
+    }
+}
@@ -0,0 +1,534 @@
+//! A linter for configuration metafields, to check a merchant's configuration
+//! offline:
+//!
+//! ```sh
+//! cargo run -- lint cart_lines_discounts_generate_run '{"orderPercentage": 150}'
+//! ```
+//!
+//! The function ignores what it can't apply, so a misconfigured discount
+//! quietly applies less than intended. The linter reports those problems for
+//! the run target it's given: values the JSON Schema rejects, like percentages
+//! over 100, negative amounts or unknown discount classes, fields the function
+//! can't read, and rules or tiers that can't apply as configured.
+
+use super::configuration::DiscountConfiguration;
+use super::json_schema::configuration_schema;
+use super::rules::{DiscountClass, Skip};
+use super::strategy::{Strategy, CART_LINES_TARGET, DELIVERY_TARGET};
+use serde_json::Value;
+use shopify_function::wasm_api::{Context, Deserialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+pub const USAGE: &str = "\
+Usage: discount-function-rs lint <target> <metafield json or file>
+
+Reports the problems of a configuration metafield for a run target, and exits
+with a non-zero exit code when there are errors.";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    /// The function won't apply the configuration as intended.
+    Error,
+    /// The configuration applies, but likely not as intended.
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub severity: Severity,
+    /// The field with the problem, like `rules[0].then.percentage`.
+    pub field: String,
+    pub message: String,
+}
+
+/// Lints the metafield in the command-line arguments, prints its problems and
+/// exits.
+pub fn main(args: &[String]) -> ! {
+    let [target, metafield] = args else {
+        eprintln!("{USAGE}");
+        process::exit(1);
+    };
+    let problems = read(metafield).and_then(|metafield| lint(target, &metafield));
+    match problems {
+        Ok(problems) if problems.is_empty() => println!("No problems found"),
+        Ok(problems) => {
+            for problem in &problems {
+                println!("{problem}");
+            }
+            if problems
+                .iter()
+                .any(|problem| problem.severity == Severity::Error)
+            {
+                process::exit(1);
+            }
+        }
+        Err(message) => {
+            eprintln!("{message}");
+            process::exit(1);
+        }
+    }
+    process::exit(0);
+}
+
+/// The problems of a configuration metafield for a run target.
+pub fn lint(target: &str, metafield: &Value) -> Result<Vec<Problem>, String> {
+    if ![CART_LINES_TARGET, DELIVERY_TARGET].contains(&target) {
+        return Err(format!(
+            "Unknown target {target}. Available targets:\n  {CART_LINES_TARGET}\n  {DELIVERY_TARGET}"
+        ));
+    }
+
+    let mut problems = vec![];
+    check_schema(&configuration_schema(), metafield, "", &mut problems);
+    if !metafield.is_object() {
+        return Ok(problems);
+    }
+    check_versions(metafield, &mut problems);
+    check_rules(metafield, &mut problems);
+    check_subtotal_tiers(metafield, &mut problems);
+
+    let context = Context::new_with_input(metafield.clone());
+    let value = context
+        .input_get()
+        .map_err(|error| format!("Failed to read the metafield: {error:?}"))?;
+    let configuration =
+        DiscountConfiguration::deserialize(&value).expect("Configurations are read leniently");
+    check_configuration(target, &configuration, &mut problems);
+    Ok(problems)
+}
+
+/// Checks `value` against the parts of the JSON Schema the configuration's
+/// schema uses.
+fn check_schema(schema: &Value, value: &Value, field: &str, problems: &mut Vec<Problem>) {
+    let mut error = |message: String| problems.push(Problem::error(field, message));
+
+    if let Some(expected) = schema["type"].as_str() {
+        let matches = match expected {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "integer" => value.is_i64() || value.is_u64(),
+            _ => value.is_number(),
+        };
+        if !matches {
+            return error(format!(
+                "expected {} {expected}, found {value}",
+                article(expected)
+            ));
+        }
+    }
+    if let Some(names) = schema["enum"].as_array() {
+        if !names.contains(value) {
+            let names: Vec<String> = names.iter().map(ToString::to_string).collect();
+            error(format!("{value} isn't one of {}", names.join(", ")));
+        }
+    }
+    if let Some(number) = value.as_f64() {
+        if schema["minimum"]
+            .as_f64()
+            .is_some_and(|minimum| number < minimum)
+        {
+            error(format!("{value} is below {}", schema["minimum"]));
+        }
+        if schema["maximum"]
+            .as_f64()
+            .is_some_and(|maximum| number > maximum)
+        {
+            error(format!("{value} is over {}", schema["maximum"]));
+        }
+    }
+    if schema["format"] == "date" && !value.as_str().is_some_and(is_date) {
+        error(format!("{value} isn't a date like 2025-11-28"));
+    }
+
+    if let Some(items) = value.as_array() {
+        for (index, item) in items.iter().enumerate() {
+            check_schema(
+                &schema["items"],
+                item,
+                &format!("{field}[{index}]"),
+                problems,
+            );
+        }
+    }
+    let Some(object) = value.as_object() else {
+        return;
+    };
+    for required in schema["required"].as_array().into_iter().flatten() {
+        if let Some(name) = required.as_str().filter(|name| !object.contains_key(*name)) {
+            problems.push(Problem::error(field, format!("missing {name}")));
+        }
+    }
+    if let Some(alternatives) = schema["oneOf"].as_array() {
+        fn required(alternative: &Value) -> &str {
+            alternative["required"][0].as_str().unwrap_or_default()
+        }
+        let present = alternatives
+            .iter()
+            .filter(|alternative| object.contains_key(required(alternative)))
+            .count();
+        if present != 1 {
+            let names: Vec<&str> = alternatives.iter().map(required).collect();
+            problems.push(Problem::error(
+                field,
+                format!("needs exactly one of {}", names.join(", ")),
+            ));
+        }
+    }
+    for (name, dependencies) in schema["dependentRequired"]
+        .as_object()
+        .into_iter()
+        .flatten()
+    {
+        for dependency in dependencies.as_array().into_iter().flatten() {
+            let dependency = dependency.as_str().unwrap_or_default();
+            if object.contains_key(name) && !object.contains_key(dependency) {
+                problems.push(Problem::error(
+                    join(field, name),
+                    format!("only applies with {dependency}"),
+                ));
+            }
+        }
+    }
+    for (name, property) in object {
+        match schema["properties"].get(name) {
+            Some(property_schema) => {
+                check_schema(property_schema, property, &join(field, name), problems)
+            }
+            None if schema["additionalProperties"] == false => problems.push(Problem::warning(
+                join(field, name),
+                "unknown field, ignored by the function",
+            )),
+            None => {}
+        }
+    }
+}
+
+/// Checks the fields only read by some versions of the metafield.
+fn check_versions(metafield: &Value, problems: &mut Vec<Problem>) {
+    let version = metafield["version"].as_u64().unwrap_or(1);
+    let reason = if version > 1 {
+        "only read by version 1 metafields, ignored"
+    } else if !metafield["rules"].is_null() {
+        "ignored, as the metafield has rules"
+    } else {
+        return;
+    };
+    for name in [
+        "cartLinePercentage",
+        "orderPercentage",
+        "deliveryPercentage",
+    ] {
+        if !metafield[name].is_null() {
+            problems.push(Problem::warning(name, reason));
+        }
+    }
+}
+
+/// Checks the conditions of the rules that can never hold.
+fn check_rules(metafield: &Value, problems: &mut Vec<Problem>) {
+    let rules = metafield["rules"].as_array().into_iter().flatten();
+    for (index, rule) in rules.enumerate() {
+        let field = format!("rules[{index}].when");
+        let when = &rule["when"];
+
+        // Shopify only answers for the metafield's top-level lists
+        for name in ["collectionIds", "productTags", "customerTags"] {
+            let Some(values) = when[name].as_array() else {
+                continue;
+            };
+            if values.is_empty() {
+                problems.push(Problem::warning(
+                    join(&field, name),
+                    "empty list, the condition always holds",
+                ));
+            }
+            let variables = metafield[name].as_array();
+            for value in values {
+                if !variables.is_some_and(|variables| variables.contains(value)) {
+                    problems.push(Problem::error(
+                        join(&field, name),
+                        format!("{value} isn't in the metafield's {name}, so it never matches"),
+                    ));
+                }
+            }
+        }
+
+        let (min_subtotal, max_subtotal) =
+            (when["minSubtotal"].as_f64(), when["maxSubtotal"].as_f64());
+        if let (Some(min_subtotal), Some(max_subtotal)) = (min_subtotal, max_subtotal) {
+            if min_subtotal > max_subtotal {
+                problems.push(Problem::error(
+                    &field,
+                    "minSubtotal is above maxSubtotal, so the rule never applies",
+                ));
+            }
+        }
+        if let (Some(starts_on), Some(ends_on)) =
+            (when["startsOn"].as_str(), when["endsOn"].as_str())
+        {
+            if starts_on > ends_on {
+                problems.push(Problem::error(
+                    &field,
+                    "startsOn is after endsOn, so the rule never applies",
+                ));
+            }
+        }
+    }
+}
+
+/// Checks the tiers that never apply, because another tier from the same or a
+/// lower subtotal gives as much.
+fn check_subtotal_tiers(metafield: &Value, problems: &mut Vec<Problem>) {
+    let tiers: Vec<(usize, f64, f64)> = metafield["subtotalTiers"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .filter_map(|(index, tier)| {
+            Some((
+                index,
+                tier["minSubtotal"].as_f64()?,
+                tier["percentage"].as_f64()?,
+            ))
+        })
+        .collect();
+
+    for &(index, min_subtotal, percentage) in &tiers {
+        let overlapping = tiers.iter().find(|&&(other, other_min_subtotal, _)| {
+            other < index && other_min_subtotal == min_subtotal
+        });
+        let better = tiers
+            .iter()
+            .find(|&&(other, other_min_subtotal, other_percentage)| {
+                other != index
+                    && other_min_subtotal < min_subtotal
+                    && other_percentage >= percentage
+            });
+        let field = format!("subtotalTiers[{index}]");
+        if let Some((other, ..)) = overlapping {
+            problems.push(Problem::error(
+                field,
+                format!("overlaps subtotalTiers[{other}], which starts at the same subtotal"),
+            ));
+        } else if let Some((other, ..)) = better {
+            problems.push(Problem::warning(
+                field,
+                format!("gives no more than subtotalTiers[{other}], from a lower subtotal"),
+            ));
+        }
+    }
+}
+
+/// Checks the configuration as the function reads it.
+fn check_configuration(
+    target: &str,
+    configuration: &DiscountConfiguration,
+    problems: &mut Vec<Problem>,
+) {
+    // A single invalid value invalidates the whole field, like a list of rules
+    for field in configuration.invalid_fields() {
+        problems.push(Problem::error(
+            field,
+            format!("the function can't read it, {}", configuration.fallback()),
+        ));
+    }
+    for (index, rule) in configuration.rules.iter().enumerate() {
+        if let Err(Skip::Invalid { field, reason }) = rule.then.target() {
+            problems.push(Problem::error(format!("rules[{index}].{field}"), reason));
+        }
+    }
+
+    let strategies = configuration.strategies();
+    let discount_classes: &[DiscountClass] = if target == CART_LINES_TARGET {
+        &[DiscountClass::Product, DiscountClass::Order]
+    } else {
+        &[DiscountClass::Shipping]
+    };
+    let has_rules = strategies.contains(&Strategy::Rules)
+        && configuration
+            .rules
+            .iter()
+            .any(|rule| discount_classes.contains(&rule.then.discount_class));
+    let has_tiers = target == CART_LINES_TARGET
+        && strategies.contains(&Strategy::SubtotalTiers)
+        && !configuration.subtotal_tiers.is_empty();
+    if !has_rules && !has_tiers {
+        problems.push(Problem::warning(
+            "jsonValue",
+            format!("no rule or strategy applies to {target}"),
+        ));
+    }
+    if !configuration.subtotal_tiers.is_empty() && !strategies.contains(&Strategy::SubtotalTiers) {
+        problems.push(Problem::warning(
+            "subtotalTiers",
+            "ignored without the SUBTOTAL_TIERS strategy",
+        ));
+    }
+}
+
+impl Problem {
+    fn error(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+
+    fn warning(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(field, message)
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let field = if self.field.is_empty() {
+            "jsonValue"
+        } else {
+            &self.field
+        };
+        write!(f, "{severity}: {field}: {}", self.message)
+    }
+}
+
+/// Reads a metafield from inline JSON or a JSON file.
+fn read(metafield: &str) -> Result<Value, String> {
+    let (json, source) = if Path::new(metafield).is_file() {
+        let json = fs::read_to_string(metafield)
+            .map_err(|error| format!("Failed to read {metafield}: {error}"))?;
+        (json, metafield)
+    } else {
+        (metafield.to_string(), "the metafield")
+    };
+    serde_json::from_str(&json).map_err(|error| format!("Invalid JSON in {source}: {error}"))
+}
+
+fn join(field: &str, name: &str) -> String {
+    if field.is_empty() {
+        name.to_string()
+    } else {
+        format!("{field}.{name}")
+    }
+}
+
+fn article(type_name: &str) -> &'static str {
+    if type_name.starts_with(['a', 'e', 'i', 'o', 'u']) {
+        "an"
+    } else {
+        "a"
+    }
+}
+
+/// Dates like `2025-11-28`, as the shop's local date is compared with them.
+fn is_date(date: &str) -> bool {
+    let bytes = date.as_bytes();
+    bytes.len() == 10
+        && bytes.iter().enumerate().all(|(index, byte)| match index {
+            4 | 7 => *byte == b'-',
+            _ => byte.is_ascii_digit(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn lines(target: &str, metafield: Value) -> Vec<String> {
+        lint(target, &metafield)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn reports_invalid_values() {
+        let metafield = json!({
+            "version": 2,
+            "colectionIds": ["gid://shopify/Collection/1"],
+            "orderPercentage": 10,
+            "rules": [
+                { "then": { "discountClass": "ORDER", "percentage": 150 } },
+                { "then": { "discountClass": "PRODUCT", "fixedAmount": -5 } },
+                { "then": { "discountClass": "BOGUS", "percentage": 10 } },
+                { "then": { "discountClass": "ORDER", "percentage": 10, "fixedAmount": 5 } }
+            ]
+        });
+
+        assert_eq!(
+            lines(CART_LINES_TARGET, metafield),
+            [
+                "warning: colectionIds: unknown field, ignored by the function",
+                "error: rules[0].then.percentage: 150 is over 100",
+                "error: rules[1].then.fixedAmount: -5 is below 0",
+                "error: rules[2].then.discountClass: \"BOGUS\" isn't one of \"PRODUCT\", \"ORDER\", \"SHIPPING\"",
+                "error: rules[3].then: needs exactly one of percentage, fixedAmount",
+                "warning: orderPercentage: only read by version 1 metafields, ignored",
+                "error: rules: the function can't read it, the field is ignored",
+                "warning: jsonValue: no rule or strategy applies to cart_lines_discounts_generate_run",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_rules_and_tiers_that_never_apply() {
+        let metafield = json!({
+            "strategies": ["SUBTOTAL_TIERS", "RULES"],
+            "collectionIds": ["gid://shopify/Collection/1"],
+            "subtotalTiers": [
+                { "minSubtotal": 100, "percentage": 10 },
+                { "minSubtotal": 200, "percentage": 5 },
+                { "minSubtotal": 100, "percentage": 15 }
+            ],
+            "rules": [
+                {
+                    "when": { "collectionIds": [], "productTags": ["sale"] },
+                    "then": { "discountClass": "PRODUCT", "percentage": 10 }
+                },
+                {
+                    "when": { "minSubtotal": 100, "maxSubtotal": 50 },
+                    "then": { "discountClass": "ORDER", "target": "CART_LINES", "percentage": 10 }
+                }
+            ]
+        });
+
+        assert_eq!(
+            lines(CART_LINES_TARGET, metafield),
+            [
+                "warning: rules[0].when.collectionIds: empty list, the condition always holds",
+                "error: rules[0].when.productTags: \"sale\" isn't in the metafield's productTags, so it never matches",
+                "error: rules[1].when: minSubtotal is above maxSubtotal, so the rule never applies",
+                "warning: subtotalTiers[1]: gives no more than subtotalTiers[0], from a lower subtotal",
+                "error: subtotalTiers[2]: overlaps subtotalTiers[0], which starts at the same subtotal",
+                "error: rules[1].then.target: CartLines isn't a target of ORDER discounts",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_configurations_without_discounts_for_the_target() {
+        let metafield = json!({ "cartLinePercentage": 20 });
+
+        assert_eq!(
+            lines(CART_LINES_TARGET, metafield.clone()),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            lines(DELIVERY_TARGET, json!({ "rules": [] })),
+            ["warning: jsonValue: no rule or strategy applies to cart_delivery_options_discounts_generate_run"]
+        );
+        assert!(lint("cart_lines_discounts_generate_fetch", &metafield).is_err());
+    }
+}
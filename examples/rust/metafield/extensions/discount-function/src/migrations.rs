@@ -0,0 +1,54 @@
+//! The migrations from older shapes of the configuration to the current one.
+//!
+//! The metafields of existing discounts can't all be rewritten when the shape
+//! of the configuration changes, so each change adds a migration instead. The
+//! migration at index `n` upgrades a configuration from version `n + 1` to the
+//! next, and a metafield runs every migration from its version on.
+
+use super::configuration::{field, DiscountConfiguration, CURRENT_VERSION};
+use super::rules::{DiscountClass, Rule};
+use discount_core::money::Percentage;
+use shopify_function::wasm_api::Value;
+
+/// Upgrades a configuration read from a metafield to the next version. A field
+/// with an invalid value is added to `invalid_fields`.
+pub type Migration = fn(&Value, &mut DiscountConfiguration, &mut Vec<String>);
+
+pub const MIGRATIONS: [Migration; CURRENT_VERSION as usize - 1] = [percentages_to_rules];
+
+/// Version 1 applies its `cartLinePercentage`, `orderPercentage` and
+/// `deliveryPercentage` when it has no `rules`, which were the only discounts
+/// of the example before rules. Version 2 has a rule for each of them instead.
+fn percentages_to_rules(
+    value: &Value,
+    configuration: &mut DiscountConfiguration,
+    invalid_fields: &mut Vec<String>,
+) {
+    let has_rules = !value.get_obj_prop("rules").is_null();
+    if has_rules && !invalid_fields.iter().any(|field| field == "rules") {
+        return;
+    }
+    let mut percentage =
+        |name: &str| -> Percentage { field(value, name, invalid_fields).unwrap_or_default() };
+    let cart_line_percentage = percentage("cartLinePercentage");
+    let order_percentage = percentage("orderPercentage");
+    let delivery_percentage = percentage("deliveryPercentage");
+    let collection_ids: Vec<String> =
+        field(value, "collectionIds", invalid_fields).unwrap_or_default();
+
+    configuration.rules = vec![
+        Rule::percentage(
+            DiscountClass::Product,
+            cart_line_percentage,
+            &collection_ids,
+            "PRODUCT",
+        ),
+        Rule::percentage(DiscountClass::Order, order_percentage, &[], "ORDER"),
+        Rule::percentage(
+            DiscountClass::Shipping,
+            delivery_percentage,
+            &[],
+            "DELIVERY",
+        ),
+    ];
+}
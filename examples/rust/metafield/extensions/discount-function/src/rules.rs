@@ -0,0 +1,686 @@
+// [START discount-function.rules]
+//! The discount rules stored in the discount's metafield.
+//!
+//! Each rule applies a discount when all of its conditions hold:
+//!
+//! ```json
+//! {
+//!   "productTags": ["sale"],
+//!   "rules": [
+//!     {
+//!       "when": { "productTags": ["sale"], "minSubtotal": 50 },
+//!       "then": {
+//!         "discountClass": "PRODUCT",
+//!         "percentage": 20,
+//!         "maxAmount": 30,
+//!         "message": "20% OFF SALE ITEMS"
+//!       }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Shopify only passes the top-level fields of the metafield to the input
+//! queries, as variables, so every collection and tag used by a rule must also
+//! be listed in the metafield's `collectionIds`, `productTags` and
+//! `customerTags`.
+
+use super::configuration::DiscountConfiguration;
+use super::strategy::{
+    CartLinesInput, DeliveryInput, DiscountStrategy, CART_LINES_TARGET, DELIVERY_TARGET,
+};
+use discount_core::cart::most_expensive_line;
+use discount_core::explain::{self, explain};
+use discount_core::money::{Money, Percentage};
+use discount_core::operations::{
+    delivery_candidate, delivery_discounts, order_discount, product_discount,
+};
+use discount_core::{schema, targets, DiscountClasses, DiscountError, ErrorKind};
+use shopify_function::prelude::*;
+use shopify_function::wasm_api::{read::Error, Deserialize, Value};
+use std::fmt;
+
+#[derive(Deserialize, Clone)]
+#[shopify_function(rename_all = "camelCase")]
+pub struct Rule {
+    #[shopify_function(default)]
+    pub when: Conditions,
+    pub then: Action,
+}
+
+impl Rule {
+    /// A rule applying `percentage` on the cart lines in `collection_ids`, or
+    /// on everything its discount class targets when there are none.
+    pub fn percentage(
+        discount_class: DiscountClass,
+        percentage: Percentage,
+        collection_ids: &[String],
+        name: &str,
+    ) -> Self {
+        let percentage = percentage.capped();
+        Self {
+            when: Conditions {
+                collection_ids: collection_ids.to_vec(),
+                ..Conditions::default()
+            },
+            then: Action {
+                discount_class,
+                target: None,
+                percentage: Some(percentage),
+                fixed_amount: None,
+                max_amount: None,
+                message: Some(format!("{percentage}% OFF {name}")),
+            },
+        }
+    }
+}
+
+/// The conditions of a rule. Conditions that aren't set always hold.
+#[derive(Deserialize, Clone, Default)]
+#[shopify_function(rename_all = "camelCase")]
+pub struct Conditions {
+    /// A cart line matches when its product is in any of these collections.
+    #[shopify_function(default)]
+    collection_ids: Vec<String>,
+    /// A cart line matches when its product has any of these tags.
+    #[shopify_function(default)]
+    product_tags: Vec<String>,
+    /// A cart line matches when its quantity is at least this one.
+    min_quantity: Option<i32>,
+    min_subtotal: Option<Money>,
+    max_subtotal: Option<Money>,
+    /// The customer has any of these tags.
+    #[shopify_function(default)]
+    customer_tags: Vec<String>,
+    /// The first and last dates of the rule, inclusive, in the shop's time zone.
+    starts_on: Option<String>,
+    ends_on: Option<String>,
+}
+
+/// The discount applied by a rule: a `percentage`, a `fixedAmount`, or a
+/// `percentage` capped at `maxAmount`.
+#[derive(Deserialize, Clone)]
+#[shopify_function(rename_all = "camelCase")]
+pub struct Action {
+    pub discount_class: DiscountClass,
+    pub target: Option<RuleTarget>,
+    percentage: Option<Percentage>,
+    fixed_amount: Option<Money>,
+    max_amount: Option<Money>,
+    message: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiscountClass {
+    Product,
+    Order,
+    Shipping,
+}
+
+impl DiscountClass {
+    pub const ALL: [Self; 3] = [Self::Product, Self::Order, Self::Shipping];
+
+    /// The name of the discount class in the metafield.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Product => "PRODUCT",
+            Self::Order => "ORDER",
+            Self::Shipping => "SHIPPING",
+        }
+    }
+}
+
+impl Deserialize for DiscountClass {
+    fn deserialize(value: &Value) -> Result<Self, Error> {
+        let name: String = Deserialize::deserialize(value)?;
+        Self::ALL
+            .into_iter()
+            .find(|discount_class| discount_class.name() == name)
+            .ok_or(Error::InvalidType)
+    }
+}
+
+impl fmt::Display for DiscountClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What a rule discounts. Each discount class has its own targets, and
+/// defaults to the first one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RuleTarget {
+    /// PRODUCT: every matching cart line.
+    CartLines,
+    /// PRODUCT: the matching cart line with the largest subtotal.
+    MostExpensiveCartLine,
+    /// ORDER: the order subtotal.
+    OrderSubtotal,
+    /// SHIPPING: the first delivery group.
+    FirstDeliveryGroup,
+    /// SHIPPING: every delivery group.
+    DeliveryGroups,
+}
+
+impl RuleTarget {
+    pub const ALL: [Self; 5] = [
+        Self::CartLines,
+        Self::MostExpensiveCartLine,
+        Self::OrderSubtotal,
+        Self::FirstDeliveryGroup,
+        Self::DeliveryGroups,
+    ];
+
+    /// The name of the target in the metafield.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::CartLines => "CART_LINES",
+            Self::MostExpensiveCartLine => "MOST_EXPENSIVE_CART_LINE",
+            Self::OrderSubtotal => "ORDER_SUBTOTAL",
+            Self::FirstDeliveryGroup => "FIRST_DELIVERY_GROUP",
+            Self::DeliveryGroups => "DELIVERY_GROUPS",
+        }
+    }
+}
+
+impl Deserialize for RuleTarget {
+    fn deserialize(value: &Value) -> Result<Self, Error> {
+        let name: String = Deserialize::deserialize(value)?;
+        Self::ALL
+            .into_iter()
+            .find(|target| target.name() == name)
+            .ok_or(Error::InvalidType)
+    }
+}
+
+/// The value of a rule's discount on a given amount.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RuleValue {
+    Percentage(Percentage),
+    FixedAmount(Money),
+}
+
+impl fmt::Display for RuleValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Percentage(percentage) => write!(f, "{percentage}% off"),
+            Self::FixedAmount(amount) => write!(f, "{amount} off"),
+        }
+    }
+}
+
+impl RuleValue {
+    pub fn product_value(self) -> schema::ProductDiscountCandidateValue {
+        match self {
+            Self::Percentage(percentage) => {
+                schema::ProductDiscountCandidateValue::Percentage(schema::Percentage {
+                    value: percentage.to_decimal(),
+                })
+            }
+            Self::FixedAmount(amount) => schema::ProductDiscountCandidateValue::FixedAmount(
+                schema::ProductDiscountCandidateFixedAmount {
+                    amount: amount.to_decimal(),
+                    applies_to_each_item: None,
+                },
+            ),
+        }
+    }
+
+    pub fn order_value(self) -> schema::OrderDiscountCandidateValue {
+        match self {
+            Self::Percentage(percentage) => {
+                schema::OrderDiscountCandidateValue::Percentage(schema::Percentage {
+                    value: percentage.to_decimal(),
+                })
+            }
+            Self::FixedAmount(amount) => {
+                schema::OrderDiscountCandidateValue::FixedAmount(schema::FixedAmount {
+                    amount: amount.to_decimal(),
+                })
+            }
+        }
+    }
+
+    pub fn delivery_value(self) -> schema::DeliveryDiscountCandidateValue {
+        match self {
+            Self::Percentage(percentage) => {
+                schema::DeliveryDiscountCandidateValue::Percentage(schema::Percentage {
+                    value: percentage.to_decimal(),
+                })
+            }
+            Self::FixedAmount(amount) => {
+                schema::DeliveryDiscountCandidateValue::FixedAmount(schema::FixedAmount {
+                    amount: amount.to_decimal(),
+                })
+            }
+        }
+    }
+}
+
+impl Action {
+    /// The target of the action, when it's one of the discount class's.
+    pub fn target(&self) -> Result<RuleTarget, Skip> {
+        use RuleTarget::*;
+        let targets: &[RuleTarget] = match self.discount_class {
+            DiscountClass::Product => &[CartLines, MostExpensiveCartLine],
+            DiscountClass::Order => &[OrderSubtotal],
+            DiscountClass::Shipping => &[FirstDeliveryGroup, DeliveryGroups],
+        };
+        match self.target {
+            None => Ok(targets[0]),
+            Some(target) if targets.contains(&target) => Ok(target),
+            Some(target) => Err(Skip::Invalid {
+                field: "then.target",
+                reason: format!(
+                    "{target:?} isn't a target of {} discounts",
+                    self.discount_class
+                ),
+            }),
+        }
+    }
+
+    /// The value of the discount on `amount`, the amount discounted by a
+    /// percentage. A capped percentage becomes a fixed amount when the
+    /// percentage of `amount` would be above the cap. Fixed amounts are
+    /// rounded to the minor unit of the cart's currency.
+    pub fn value(
+        &self,
+        input: &impl RuleInput,
+        amount: impl FnOnce() -> Option<Money>,
+    ) -> Result<RuleValue, Skip> {
+        let fixed_amount = |field, amount: Money| {
+            let currency_code = input.currency_code();
+            match amount.rounded(currency_code) {
+                rounded if rounded.is_positive() => Ok(RuleValue::FixedAmount(rounded)),
+                _ => Err(Skip::Invalid {
+                    field,
+                    reason: format!("{amount} rounds to 0 {currency_code}"),
+                }),
+            }
+        };
+        match (self.percentage, self.fixed_amount) {
+            (Some(_), Some(_)) => Err(Skip::Invalid {
+                field: "then",
+                reason: "both percentage and fixedAmount are set".to_string(),
+            }),
+            (None, None) => Err(Skip::Invalid {
+                field: "then",
+                reason: "neither percentage nor fixedAmount is set".to_string(),
+            }),
+            (None, Some(amount)) if !amount.is_positive() => {
+                Err(format!("fixedAmount is {amount}").into())
+            }
+            (None, Some(amount)) => fixed_amount("then.fixedAmount", amount),
+            (Some(percentage), None) => {
+                let percentage = percentage.capped();
+                if !percentage.is_positive() {
+                    return Err(format!("percentage is {percentage}").into());
+                }
+                let Some(max_amount) = self.max_amount else {
+                    return Ok(RuleValue::Percentage(percentage));
+                };
+                match amount() {
+                    Some(amount) if amount.percentage(percentage) > max_amount => {
+                        fixed_amount("then.maxAmount", max_amount)
+                    }
+                    _ => Ok(RuleValue::Percentage(percentage)),
+                }
+            }
+        }
+    }
+
+    /// The message of the discount, or one describing its value.
+    pub fn message(&self, value: RuleValue) -> String {
+        match &self.message {
+            Some(message) => message.clone(),
+            None => format!("{} {}", value, self.discount_class).to_uppercase(),
+        }
+    }
+}
+
+/// Why a rule doesn't apply.
+#[derive(Debug)]
+pub enum Skip {
+    /// The rule doesn't apply to this cart, such as when a condition doesn't
+    /// hold or its value is 0.
+    NotApplicable(String),
+    /// The rule can't be applied as it's configured.
+    Invalid { field: &'static str, reason: String },
+}
+
+impl From<String> for Skip {
+    fn from(reason: String) -> Self {
+        Self::NotApplicable(reason)
+    }
+}
+
+impl Skip {
+    /// Explains why the rule at `index` of the configuration was skipped. An
+    /// invalid rule is an `INVALID_CONFIGURATION` error, whose policy skips it
+    /// and applies the other rules.
+    fn explain(self, target: &'static str, index: usize) {
+        let number = index + 1;
+        match self {
+            Self::NotApplicable(reason) => explain!("rule {number} skipped: {reason}"),
+            Self::Invalid { field, reason } => {
+                let error = DiscountError::new(
+                    target,
+                    ErrorKind::InvalidConfiguration {
+                        field: format!("rules[{index}].{field}"),
+                        reason,
+                    },
+                );
+                explain!("rule {number} skipped: {}", error.details());
+            }
+        }
+    }
+}
+
+/// The parts of a target's input read by the rules' conditions. The fields are
+/// only read when a condition needs them.
+pub trait RuleInput {
+    type Line: RuleLine;
+
+    fn lines(&self) -> &[Self::Line];
+    /// `None` when the subtotal isn't a number.
+    fn subtotal(&self) -> Option<Money>;
+    fn currency_code(&self) -> &str;
+    fn customer_has_any_tag(&self, tags: &[String]) -> bool;
+    /// The date in the shop's time zone, as `YYYY-MM-DD`.
+    fn local_date(&self) -> &str;
+}
+
+pub trait RuleLine {
+    fn id(&self) -> &str;
+    fn quantity(&self) -> i32;
+    fn subtotal(&self) -> Option<Money>;
+    fn in_any_collection(&self, collection_ids: &[String]) -> bool;
+    fn has_any_tag(&self, tags: &[String]) -> bool;
+}
+
+impl Conditions {
+    /// Whether the conditions hold, with at least one cart line matching the
+    /// conditions on cart lines, if any, or why the rule doesn't apply.
+    pub fn hold(&self, input: &impl RuleInput) -> Result<(), String> {
+        self.check_cart(input)?;
+        if self.has_line_conditions() {
+            self.matching_lines(input)?;
+        }
+        Ok(())
+    }
+
+    /// The cart lines matching the conditions on cart lines, when the conditions
+    /// on the cart hold, or why the rule doesn't apply.
+    pub fn matching_lines<'a, I: RuleInput>(
+        &self,
+        input: &'a I,
+    ) -> Result<Vec<&'a I::Line>, String> {
+        self.check_cart(input)?;
+        let lines: Vec<&I::Line> = input
+            .lines()
+            .iter()
+            .filter(|line| self.matches_line(*line))
+            .collect();
+        if lines.is_empty() {
+            return Err("no cart line matches the rule".to_string());
+        }
+        Ok(lines)
+    }
+
+    fn check_cart(&self, input: &impl RuleInput) -> Result<(), String> {
+        if self.min_subtotal.is_some() || self.max_subtotal.is_some() {
+            let Some(subtotal) = input.subtotal() else {
+                return Err("the cart subtotal isn't a number".to_string());
+            };
+            if let Some(min_subtotal) = self.min_subtotal.filter(|min| subtotal < *min) {
+                return Err(format!("cart subtotal {subtotal} is below {min_subtotal}"));
+            }
+            if let Some(max_subtotal) = self.max_subtotal.filter(|max| subtotal > *max) {
+                return Err(format!("cart subtotal {subtotal} is above {max_subtotal}"));
+            }
+        }
+        if !self.customer_tags.is_empty() && !input.customer_has_any_tag(&self.customer_tags) {
+            return Err(format!(
+                "the customer has none of the tags {}",
+                explain::list(&self.customer_tags)
+            ));
+        }
+        if self.starts_on.is_some() || self.ends_on.is_some() {
+            let date = input.local_date();
+            if let Some(starts_on) = self.starts_on.as_deref().filter(|start| date < *start) {
+                return Err(format!("the rule starts on {starts_on}, it's {date}"));
+            }
+            if let Some(ends_on) = self.ends_on.as_deref().filter(|end| date > *end) {
+                return Err(format!("the rule ended on {ends_on}, it's {date}"));
+            }
+        }
+        Ok(())
+    }
+
+    fn has_line_conditions(&self) -> bool {
+        !self.collection_ids.is_empty()
+            || !self.product_tags.is_empty()
+            || self.min_quantity.is_some()
+    }
+
+    fn matches_line(&self, line: &impl RuleLine) -> bool {
+        (self.collection_ids.is_empty() || line.in_any_collection(&self.collection_ids))
+            && (self.product_tags.is_empty() || line.has_any_tag(&self.product_tags))
+            && self
+                .min_quantity
+                .is_none_or(|min_quantity| line.quantity() >= min_quantity)
+    }
+}
+
+/// Applies each rule of the configuration, in order. Each rule that applies
+/// adds its own operation.
+pub struct Rules;
+
+impl DiscountStrategy for Rules {
+    fn cart_lines_operations(
+        &self,
+        input: &CartLinesInput,
+        configuration: &DiscountConfiguration,
+        discount_classes: DiscountClasses,
+    ) -> Vec<schema::CartOperation> {
+        let mut operations = vec![];
+        for (index, rule) in configuration.rules.iter().enumerate() {
+            let number = index + 1;
+            let discount_class = rule.then.discount_class;
+            let has_discount_class = match discount_class {
+                DiscountClass::Product => discount_classes.product,
+                DiscountClass::Order => discount_classes.order,
+                DiscountClass::Shipping => continue,
+            };
+            if !has_discount_class {
+                explain!("rule {number} skipped: no {discount_class} discount class");
+                continue;
+            }
+
+            match cart_rule_operation(input, rule) {
+                Ok((operation, description)) => {
+                    explain!("rule {number}: {description}");
+                    operations.push(operation);
+                }
+                Err(skip) => skip.explain(CART_LINES_TARGET, index),
+            }
+        }
+        operations
+    }
+
+    fn delivery_operations(
+        &self,
+        input: &DeliveryInput,
+        configuration: &DiscountConfiguration,
+        discount_classes: DiscountClasses,
+    ) -> Vec<schema::DeliveryOperation> {
+        let mut operations = vec![];
+        for (index, rule) in configuration.rules.iter().enumerate() {
+            let number = index + 1;
+            if rule.then.discount_class != DiscountClass::Shipping || !discount_classes.shipping {
+                continue;
+            }
+
+            match delivery_rule_operation(input, rule) {
+                Ok((operation, description)) => {
+                    explain!("rule {number}: {description}");
+                    operations.push(operation);
+                }
+                Err(skip) => skip.explain(DELIVERY_TARGET, index),
+            }
+        }
+        operations
+    }
+}
+
+/// The operation of a PRODUCT or ORDER rule, with a description for the
+/// explain trace, or why the rule doesn't apply.
+fn cart_rule_operation(
+    input: &CartLinesInput,
+    rule: &Rule,
+) -> Result<(schema::CartOperation, String), Skip> {
+    match rule.then.target()? {
+        RuleTarget::OrderSubtotal => {
+            rule.when.hold(input)?;
+            let value = rule.then.value(input, || input.subtotal())?;
+            let operation = order_discount(value.order_value(), rule.then.message(value));
+            Ok((operation, format!("{value} the order subtotal")))
+        }
+        target => {
+            let mut lines = rule.when.matching_lines(input)?;
+            if target == RuleTarget::MostExpensiveCartLine {
+                lines = most_expensive_line(&lines, |line| line.subtotal())
+                    .into_iter()
+                    .copied()
+                    .collect();
+            }
+            let value = rule
+                .then
+                .value(input, || lines.iter().map(|line| line.subtotal()).sum())?;
+            let description = format!("{value} {} cart lines", lines.len());
+            let targets = lines
+                .iter()
+                .map(|line| targets::cart_line(RuleLine::id(*line)))
+                .collect();
+            let operation =
+                product_discount(targets, value.product_value(), rule.then.message(value))
+                    .ok_or_else(|| "no cart line matches the rule".to_string())?;
+            Ok((operation, description))
+        }
+    }
+}
+
+/// The operation of a SHIPPING rule, with a description for the explain
+/// trace, or why the rule doesn't apply. A capped percentage is capped on the
+/// cost of each group's selected delivery option.
+fn delivery_rule_operation(
+    input: &DeliveryInput,
+    rule: &Rule,
+) -> Result<(schema::DeliveryOperation, String), Skip> {
+    let delivery_groups = input.cart().delivery_groups();
+    let delivery_groups = match rule.then.target()? {
+        RuleTarget::FirstDeliveryGroup => &delivery_groups[..1],
+        _ => delivery_groups,
+    };
+    rule.when.hold(input)?;
+
+    let mut candidates = vec![];
+    let mut descriptions = vec![];
+    for delivery_group in delivery_groups {
+        let value = rule.then.value(input, || {
+            delivery_group
+                .selected_delivery_option()
+                .and_then(|delivery_option| Money::from_decimal(*delivery_option.cost().amount()))
+        })?;
+        descriptions.push(format!("{value} delivery group {:?}", delivery_group.id()));
+        candidates.push(delivery_candidate(
+            targets::delivery_group(delivery_group.id()),
+            value.delivery_value(),
+            rule.then.message(value),
+        ));
+    }
+    Ok((delivery_discounts(candidates), descriptions.join(", ")))
+}
+
+/// Implements the rule traits for the input of a target's query.
+macro_rules! impl_rule_input {
+    ($query:ident) => {
+        impl RuleInput for crate::schema::$query::Input {
+            type Line = crate::schema::$query::input::cart::Lines;
+
+            fn lines(&self) -> &[Self::Line] {
+                self.cart().lines()
+            }
+
+            fn subtotal(&self) -> Option<Money> {
+                Money::from_decimal(*self.cart().cost().subtotal_amount().amount())
+            }
+
+            fn currency_code(&self) -> &str {
+                self.cart().cost().subtotal_amount().currency_code()
+            }
+
+            fn customer_has_any_tag(&self, tags: &[String]) -> bool {
+                let Some(customer) = self
+                    .cart()
+                    .buyer_identity()
+                    .and_then(|buyer_identity| buyer_identity.customer())
+                else {
+                    return false;
+                };
+                customer
+                    .has_tags()
+                    .iter()
+                    .any(|response| *response.has_tag() && tags.contains(response.tag()))
+            }
+
+            fn local_date(&self) -> &str {
+                self.shop().local_time().date()
+            }
+        }
+
+        impl RuleLine for crate::schema::$query::input::cart::Lines {
+            fn id(&self) -> &str {
+                self.id()
+            }
+
+            fn quantity(&self) -> i32 {
+                *self.quantity()
+            }
+
+            fn subtotal(&self) -> Option<Money> {
+                Money::from_decimal(*self.cost().subtotal_amount().amount())
+            }
+
+            fn in_any_collection(&self, collection_ids: &[String]) -> bool {
+                let crate::schema::$query::input::cart::lines::Merchandise::ProductVariant(
+                    variant,
+                ) = self.merchandise()
+                else {
+                    return false;
+                };
+                variant.product().in_collections().iter().any(|membership| {
+                    *membership.is_member() && collection_ids.contains(membership.collection_id())
+                })
+            }
+
+            fn has_any_tag(&self, tags: &[String]) -> bool {
+                let crate::schema::$query::input::cart::lines::Merchandise::ProductVariant(
+                    variant,
+                ) = self.merchandise()
+                else {
+                    return false;
+                };
+                variant
+                    .product()
+                    .has_tags()
+                    .iter()
+                    .any(|response| *response.has_tag() && tags.contains(response.tag()))
+            }
+        }
+    };
+}
+
+impl_rule_input!(cart_lines_discounts_generate_run);
+impl_rule_input!(cart_delivery_options_discounts_generate_run);
+// [END discount-function.rules]
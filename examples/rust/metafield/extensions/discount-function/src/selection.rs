@@ -0,0 +1,119 @@
+// [START discount-function.selection]
+//! How Shopify selects among the discounts of the run targets.
+//!
+//! By default, every rule and strategy adds its own operation, and all of
+//! their discounts apply. `selectionStrategies` merges the candidates of the
+//! product or order discounts into a single operation with a selection
+//! strategy instead. Shopify then applies `ALL` of them, the `FIRST` one, in
+//! the order of the strategies and rules, or the `MAXIMUM` one, the best for
+//! the customer:
+//!
+//! ```json
+//! {
+//!   "selectionStrategies": { "product": "ALL", "order": "MAXIMUM" }
+//! }
+//! ```
+//!
+//! Order discount operations have no `ALL` selection strategy, so with `ALL`,
+//! each order discount stays in its own operation, and all of them apply like
+//! by default. Delivery discounts always apply `ALL` of their candidates, the
+//! only selection strategy of delivery operations.
+
+use discount_core::explain::explain;
+use discount_core::operations::{merge_order_discounts, merge_product_discounts};
+use discount_core::schema;
+use shopify_function::prelude::*;
+use shopify_function::wasm_api::{read::Error, Deserialize, Value};
+use std::fmt;
+
+/// The selection strategies of the product and order discounts, when they
+/// are merged into a single operation.
+#[derive(Deserialize, Clone, Copy, Default)]
+#[shopify_function(rename_all = "camelCase")]
+pub struct SelectionStrategies {
+    pub product: Option<SelectionStrategy>,
+    pub order: Option<SelectionStrategy>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SelectionStrategy {
+    /// Every discount that applies.
+    All,
+    /// Only the first discount that applies.
+    First,
+    /// Only the discount with the largest reduction.
+    Maximum,
+}
+
+impl SelectionStrategies {
+    /// Merges the candidates of the operations whose discounts compete.
+    pub fn apply(self, mut operations: Vec<schema::CartOperation>) -> Vec<schema::CartOperation> {
+        if let Some(product) = self.product {
+            explain!("selection strategy of product discounts: {product}");
+            operations = merge_product_discounts(operations, product.product());
+        }
+
+        match self.order.map(|order| (order, order.order())) {
+            Some((order, Some(selection_strategy))) => {
+                explain!("selection strategy of order discounts: {order}");
+                operations = merge_order_discounts(operations, selection_strategy);
+            }
+            Some((order, None)) => {
+                explain!(
+                    "selection strategy of order discounts: {order}, each in its own operation"
+                );
+            }
+            None => {}
+        }
+        operations
+    }
+}
+
+impl SelectionStrategy {
+    pub const ALL: [Self; 3] = [Self::All, Self::First, Self::Maximum];
+
+    /// The selection strategy of a product discount operation.
+    fn product(self) -> schema::ProductDiscountSelectionStrategy {
+        match self {
+            Self::All => schema::ProductDiscountSelectionStrategy::All,
+            Self::First => schema::ProductDiscountSelectionStrategy::First,
+            Self::Maximum => schema::ProductDiscountSelectionStrategy::Maximum,
+        }
+    }
+
+    /// The selection strategy of an order discount operation, or `None` for
+    /// `All`, which order discount operations don't have.
+    fn order(self) -> Option<schema::OrderDiscountSelectionStrategy> {
+        match self {
+            Self::All => None,
+            Self::First => Some(schema::OrderDiscountSelectionStrategy::First),
+            Self::Maximum => Some(schema::OrderDiscountSelectionStrategy::Maximum),
+        }
+    }
+
+    /// The name of the selection strategy in the metafield.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::All => "ALL",
+            Self::First => "FIRST",
+            Self::Maximum => "MAXIMUM",
+        }
+    }
+}
+
+impl Deserialize for SelectionStrategy {
+    fn deserialize(value: &Value) -> Result<Self, Error> {
+        let name: String = Deserialize::deserialize(value)?;
+        Self::ALL
+            .into_iter()
+            .find(|selection_strategy| selection_strategy.name() == name)
+            .ok_or(Error::InvalidType)
+    }
+}
+
+impl fmt::Display for SelectionStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+// [END discount-function.selection]
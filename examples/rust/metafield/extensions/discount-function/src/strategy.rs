@@ -0,0 +1,93 @@
+// [START discount-function.strategy]
+//! The discount strategies applied by the run targets.
+//!
+//! A strategy turns the configuration into discount operations. The metafield
+//! selects the strategies and their order, and each run target adds the
+//! operations of every strategy it selects. A new kind of promotion is a file
+//! implementing [`DiscountStrategy`], plus its variant of [`Strategy`].
+
+use super::configuration::DiscountConfiguration;
+use super::rules::Rules;
+use super::subtotal_tiers::SubtotalTiers;
+use discount_core::{schema, DiscountClasses};
+use shopify_function::wasm_api::{read::Error, Deserialize, Value};
+use std::fmt;
+
+/// The name of the cart lines run target, for errors.
+pub const CART_LINES_TARGET: &str = "cart_lines_discounts_generate_run";
+/// The name of the delivery options run target, for errors.
+pub const DELIVERY_TARGET: &str = "cart_delivery_options_discounts_generate_run";
+
+/// The input of the cart lines run target.
+pub type CartLinesInput = super::schema::cart_lines_discounts_generate_run::Input;
+/// The input of the delivery options run target.
+pub type DeliveryInput = super::schema::cart_delivery_options_discounts_generate_run::Input;
+
+/// A way of discounting a cart. Both methods add no operations by default, so
+/// a strategy only implements the run targets it discounts.
+pub trait DiscountStrategy {
+    /// The PRODUCT and ORDER operations of the strategy.
+    fn cart_lines_operations(
+        &self,
+        _input: &CartLinesInput,
+        _configuration: &DiscountConfiguration,
+        _discount_classes: DiscountClasses,
+    ) -> Vec<schema::CartOperation> {
+        vec![]
+    }
+
+    /// The SHIPPING operations of the strategy. The cart has at least one
+    /// delivery group.
+    fn delivery_operations(
+        &self,
+        _input: &DeliveryInput,
+        _configuration: &DiscountConfiguration,
+        _discount_classes: DiscountClasses,
+    ) -> Vec<schema::DeliveryOperation> {
+        vec![]
+    }
+}
+
+/// The strategies a metafield can select.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    Rules,
+    SubtotalTiers,
+}
+
+impl Strategy {
+    pub const ALL: [Self; 2] = [Self::Rules, Self::SubtotalTiers];
+
+    /// The implementation of the strategy.
+    pub fn implementation(self) -> &'static dyn DiscountStrategy {
+        match self {
+            Self::Rules => &Rules,
+            Self::SubtotalTiers => &SubtotalTiers,
+        }
+    }
+
+    /// The name of the strategy in the metafield.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Rules => "RULES",
+            Self::SubtotalTiers => "SUBTOTAL_TIERS",
+        }
+    }
+}
+
+impl Deserialize for Strategy {
+    fn deserialize(value: &Value) -> Result<Self, Error> {
+        let name: String = Deserialize::deserialize(value)?;
+        Self::ALL
+            .into_iter()
+            .find(|strategy| strategy.name() == name)
+            .ok_or(Error::InvalidType)
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+// [END discount-function.strategy]
@@ -0,0 +1,122 @@
+// [START discount-function.subtotal-tiers]
+//! An order discount growing with the cart subtotal.
+//!
+//! Each tier applies its percentage from its minimum subtotal, and only the
+//! highest tier the cart reaches applies:
+//!
+//! ```json
+//! {
+//!   "strategies": ["SUBTOTAL_TIERS"],
+//!   "subtotalTiers": [
+//!     { "minSubtotal": 100, "percentage": 5 },
+//!     { "minSubtotal": 200, "percentage": 10, "message": "10% OFF ORDERS OVER 200" }
+//!   ]
+//! }
+//! ```
+
+use super::configuration::DiscountConfiguration;
+use super::rules::RuleInput;
+use super::strategy::{CartLinesInput, DiscountStrategy};
+use discount_core::explain::explain;
+use discount_core::money::{Money, Percentage};
+use discount_core::operations::order_percentage_discount;
+use discount_core::{schema, DiscountClasses};
+use shopify_function::prelude::*;
+
+#[derive(Deserialize, Clone)]
+#[shopify_function(rename_all = "camelCase")]
+pub struct SubtotalTier {
+    min_subtotal: Money,
+    percentage: Percentage,
+    message: Option<String>,
+}
+
+/// Applies the highest subtotal tier the cart reaches.
+pub struct SubtotalTiers;
+
+impl DiscountStrategy for SubtotalTiers {
+    fn cart_lines_operations(
+        &self,
+        input: &CartLinesInput,
+        configuration: &DiscountConfiguration,
+        discount_classes: DiscountClasses,
+    ) -> Vec<schema::CartOperation> {
+        if configuration.subtotal_tiers.is_empty() {
+            return vec![];
+        }
+        if !discount_classes.order {
+            explain!("subtotal tiers skipped: no ORDER discount class");
+            return vec![];
+        }
+
+        let Some(subtotal) = input.subtotal() else {
+            explain!("subtotal tiers skipped: the cart subtotal isn't a number");
+            return vec![];
+        };
+        let tier = configuration
+            .subtotal_tiers
+            .iter()
+            .filter(|tier| tier.min_subtotal <= subtotal)
+            .max_by_key(|tier| tier.min_subtotal);
+        let Some(tier) = tier else {
+            explain!("subtotal tiers skipped: cart subtotal {subtotal} reaches no tier");
+            return vec![];
+        };
+
+        let percentage = tier.percentage.capped();
+        if !percentage.is_positive() {
+            explain!(
+                "subtotal tiers skipped: the tier from {} has no percentage",
+                tier.min_subtotal
+            );
+            return vec![];
+        }
+        explain!(
+            "subtotal tiers: {percentage}% off the order subtotal, from {}",
+            tier.min_subtotal
+        );
+        let message = tier
+            .message
+            .clone()
+            .unwrap_or_else(|| format!("{percentage}% OFF ORDER"));
+        vec![order_percentage_discount(percentage, message)]
+    }
+}
+// [END discount-function.subtotal-tiers]
+
+#[cfg(test)]
+mod tests {
+    use crate::cart_lines_discounts_generate_run::cart_lines_discounts_generate_run;
+    use discount_core::explain;
+    use discount_function_devtools::cart_builder::CartBuilder;
+    use discount_function_devtools::run_to_json;
+    use serde_json::json;
+
+    #[test]
+    fn applies_the_highest_tier_reached() {
+        let input = CartBuilder::new()
+            .line("gid://shopify/CartLine/0", 150.0)
+            .line("gid://shopify/CartLine/1", 100.0)
+            .discount_classes(["ORDER"])
+            .metafield(json!({
+                "strategies": ["SUBTOTAL_TIERS"],
+                "subtotalTiers": [
+                    { "minSubtotal": 100, "percentage": 5 },
+                    { "minSubtotal": 500, "percentage": 20 },
+                    { "minSubtotal": 200, "percentage": 10, "message": "10% OFF ORDERS OVER 200" }
+                ]
+            }))
+            .build();
+        explain::take_trace();
+
+        let output = run_to_json(cart_lines_discounts_generate_run, &input);
+
+        assert_eq!(
+            output["operations"][0]["orderDiscountsAdd"]["candidates"][0]["message"],
+            "10% OFF ORDERS OVER 200"
+        );
+        assert_eq!(output["operations"].as_array().map(Vec::len), Some(1));
+        assert!(explain::take_trace()
+            .contains(&"subtotal tiers: 10% off the order subtotal, from 200".to_string()));
+    }
+}
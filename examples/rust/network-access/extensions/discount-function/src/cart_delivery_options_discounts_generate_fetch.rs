@@ -1,34 +1,99 @@
 // [START discount-function.delivery.fetch]
-use super::schema;
+use super::fetch_policy::{FetchConditions, FetchConfiguration};
+use super::request_fingerprint::{request_headers, RequestFingerprint};
+use super::schema::cart_delivery_options_discounts_generate_fetch as query;
+use discount_core::money::Money;
+use discount_core::schema;
 use shopify_function;
 use shopify_function::prelude::*;
 use std::collections::BTreeMap;
 #[shopify_function]
-fn cart_delivery_options_discounts_generate_fetch(
-    input: schema::cart_delivery_options_discounts_generate_fetch::Input,
+pub(crate) fn cart_delivery_options_discounts_generate_fetch(
+    input: query::Input,
 ) -> shopify_function::Result<schema::CartDeliveryOptionsDiscountsGenerateFetchResult> {
     let entered_discount_codes = &input.entered_discount_codes();
-    let json_body = JsonValue::Object(BTreeMap::from([(
-        "enteredDiscountCodes".to_string(),
-        JsonValue::Array(
-            entered_discount_codes
-                .iter()
-                .map(|s| JsonValue::String(s.clone()))
-                .collect(),
+
+    // [START discount-function.delivery.fetch.policy]
+    let default_configuration = FetchConfiguration::default();
+    let fetch_configuration = match input.discount().metafield() {
+        Some(metafield) => metafield.json_value(),
+        None => &default_configuration,
+    };
+
+    let fetch_conditions = FetchConditions {
+        has_discount_codes: !entered_discount_codes.is_empty(),
+        subtotal: Money::from_decimal(*input.cart().cost().subtotal_amount().amount()),
+        is_authenticated_buyer: input
+            .cart()
+            .buyer_identity()
+            .is_some_and(|buyer_identity| *buyer_identity.is_authenticated()),
+    };
+
+    // Skip the network call when the server response can't change the outcome
+    if !fetch_configuration
+        .fetch_policy
+        .should_fetch(&fetch_conditions)
+    {
+        return Ok(schema::CartDeliveryOptionsDiscountsGenerateFetchResult { request: None });
+    }
+    // [END discount-function.delivery.fetch.policy]
+
+    // [START discount-function.delivery.fetch.json-body]
+    let json_body = JsonValue::Object(BTreeMap::from([
+        (
+            "enteredDiscountCodes".to_string(),
+            JsonValue::Array(
+                entered_discount_codes
+                    .iter()
+                    .map(|s| JsonValue::String(s.clone()))
+                    .collect(),
+            ),
+        ),
+        (
+            "deliveryGroups".to_string(),
+            JsonValue::Array(
+                input
+                    .cart()
+                    .delivery_groups()
+                    .iter()
+                    .map(delivery_group_json)
+                    .collect(),
+            ),
         ),
-    )]));
+    ]));
+    // [END discount-function.delivery.fetch.json-body]
+
+    // [START discount-function.delivery.fetch.headers]
+    // Shopify's amounts are always numbers
+    let mut fingerprint = RequestFingerprint::default();
+    for line in input.cart().lines() {
+        fingerprint.cart_line(
+            line.id(),
+            *line.quantity(),
+            Money::from_decimal(*line.cost().subtotal_amount().amount()).unwrap_or_default(),
+        );
+    }
+    for code in entered_discount_codes.iter() {
+        fingerprint.discount_code(code);
+    }
+    for delivery_group in input.cart().delivery_groups() {
+        for delivery_option in delivery_group.delivery_options() {
+            fingerprint.delivery_option(
+                delivery_group.id(),
+                delivery_option.handle(),
+                Money::from_decimal(*delivery_option.cost().amount()).unwrap_or_default(),
+            );
+        }
+    }
+    let correlation_id = input
+        .cart()
+        .attribute()
+        .and_then(|attribute| attribute.value().cloned());
+    let headers = request_headers(&mut fingerprint, correlation_id);
+    // [END discount-function.delivery.fetch.headers]
 
     let request = schema::HttpRequest {
-        headers: vec![
-            schema::HttpRequestHeader {
-                name: "accept".to_string(),
-                value: "application/json".to_string(),
-            },
-            schema::HttpRequestHeader {
-                name: "Content-Type".to_string(),
-                value: "application/json".to_string(),
-            },
-        ],
+        headers,
         method: schema::HttpRequestMethod::Post,
         policy: schema::HttpRequestPolicy {
             read_timeout_ms: 2000,
@@ -46,9 +111,91 @@ fn cart_delivery_options_discounts_generate_fetch(
 }
 // [END discount-function.delivery.fetch]
 
+// [START discount-function.delivery.fetch.delivery-groups]
+fn delivery_group_json(delivery_group: &query::input::cart::DeliveryGroups) -> JsonValue {
+    let delivery_address = match delivery_group.delivery_address() {
+        Some(address) => JsonValue::Object(BTreeMap::from([
+            ("city".to_string(), optional_string(address.city())),
+            (
+                "countryCode".to_string(),
+                optional_string(address.country_code()),
+            ),
+            (
+                "provinceCode".to_string(),
+                optional_string(address.province_code()),
+            ),
+            ("zip".to_string(), optional_string(address.zip())),
+        ])),
+        None => JsonValue::Null,
+    };
+
+    let delivery_options = delivery_group
+        .delivery_options()
+        .iter()
+        .map(|delivery_option| {
+            JsonValue::Object(BTreeMap::from([
+                (
+                    "handle".to_string(),
+                    JsonValue::String(delivery_option.handle().clone()),
+                ),
+                (
+                    "title".to_string(),
+                    optional_string(delivery_option.title()),
+                ),
+                (
+                    "deliveryMethodType".to_string(),
+                    JsonValue::String(delivery_option.delivery_method_type().to_string()),
+                ),
+                (
+                    "cost".to_string(),
+                    JsonValue::Object(BTreeMap::from([
+                        (
+                            "amount".to_string(),
+                            JsonValue::String(delivery_option.cost().amount().to_string()),
+                        ),
+                        (
+                            "currencyCode".to_string(),
+                            JsonValue::String(delivery_option.cost().currency_code().clone()),
+                        ),
+                    ])),
+                ),
+            ]))
+        })
+        .collect();
+
+    let selected_delivery_option_handle = optional_string(
+        delivery_group
+            .selected_delivery_option()
+            .map(|delivery_option| delivery_option.handle()),
+    );
+
+    JsonValue::Object(BTreeMap::from([
+        (
+            "id".to_string(),
+            JsonValue::String(delivery_group.id().clone()),
+        ),
+        ("deliveryAddress".to_string(), delivery_address),
+        (
+            "deliveryOptions".to_string(),
+            JsonValue::Array(delivery_options),
+        ),
+        (
+            "selectedDeliveryOptionHandle".to_string(),
+            selected_delivery_option_handle,
+        ),
+    ]))
+}
+
+fn optional_string(value: Option<&String>) -> JsonValue {
+    value.map_or(JsonValue::Null, |value| JsonValue::String(value.clone()))
+}
+// [END discount-function.delivery.fetch.delivery-groups]
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::request_fingerprint::FINGERPRINT_HEADER;
+    use discount_function_devtools::fixtures::run_fixtures;
     use serde_json::json;
     use shopify_function::run_function_with_input;
 
@@ -57,17 +204,28 @@ mod tests {
         let input = json!({
             "enteredDiscountCodes": [],
             "cart": {
-                "lines": []
+                "attribute": null,
+                "lines": [],
+                "deliveryGroups": [],
+                "buyerIdentity": null,
+                "cost": {
+                    "subtotalAmount": {
+                        "amount": "0.0"
+                    }
+                }
+            },
+            "discount": {
+                "metafield": null
             }
         })
         .to_string();
 
         let result =
             run_function_with_input(cart_delivery_options_discounts_generate_fetch, &input)?;
-        let json_body = JsonValue::Object(BTreeMap::from([(
-            "enteredDiscountCodes".to_string(),
-            JsonValue::Array(vec![]),
-        )]));
+        let json_body = JsonValue::Object(BTreeMap::from([
+            ("enteredDiscountCodes".to_string(), JsonValue::Array(vec![])),
+            ("deliveryGroups".to_string(), JsonValue::Array(vec![])),
+        ]));
         let expected = schema::CartDeliveryOptionsDiscountsGenerateFetchResult {
             request: Some(schema::HttpRequest {
                 headers: vec![
@@ -79,6 +237,10 @@ mod tests {
                         name: "Content-Type".to_string(),
                         value: "application/json".to_string(),
                     },
+                    schema::HttpRequestHeader {
+                        name: FINGERPRINT_HEADER.to_string(),
+                        value: "cbf29ce484222325".to_string(),
+                    },
                 ],
                 method: schema::HttpRequestMethod::Post,
                 policy: schema::HttpRequestPolicy {
@@ -93,4 +255,112 @@ mod tests {
         assert_eq!(result, expected);
         Ok(())
     }
+
+    #[test]
+    fn adds_delivery_groups_to_json_body() -> shopify_function::Result<()> {
+        let input = json!({
+            "enteredDiscountCodes": [],
+            "cart": {
+                "attribute": null,
+                "lines": [],
+                "deliveryGroups": [
+                    {
+                        "id": "gid://shopify/CartDeliveryGroup/0",
+                        "deliveryAddress": {
+                            "city": "Ottawa",
+                            "countryCode": "CA",
+                            "provinceCode": "ON",
+                            "zip": null
+                        },
+                        "deliveryOptions": [
+                            {
+                                "handle": "standard",
+                                "title": "Standard",
+                                "deliveryMethodType": "SHIPPING",
+                                "cost": {
+                                    "amount": "7.5",
+                                    "currencyCode": "CAD"
+                                }
+                            }
+                        ],
+                        "selectedDeliveryOption": {
+                            "handle": "standard"
+                        }
+                    }
+                ],
+                "buyerIdentity": null,
+                "cost": {
+                    "subtotalAmount": {
+                        "amount": "0.0"
+                    }
+                }
+            },
+            "discount": {
+                "metafield": null
+            }
+        })
+        .to_string();
+
+        let result =
+            run_function_with_input(cart_delivery_options_discounts_generate_fetch, &input)?;
+        let json_body = result
+            .request
+            .and_then(|request| request.json_body)
+            .expect("Expected a JSON body");
+
+        let string = |value: &str| JsonValue::String(value.to_string());
+        let expected_delivery_groups = JsonValue::Array(vec![JsonValue::Object(BTreeMap::from([
+            (
+                "id".to_string(),
+                string("gid://shopify/CartDeliveryGroup/0"),
+            ),
+            (
+                "deliveryAddress".to_string(),
+                JsonValue::Object(BTreeMap::from([
+                    ("city".to_string(), string("Ottawa")),
+                    ("countryCode".to_string(), string("CA")),
+                    ("provinceCode".to_string(), string("ON")),
+                    ("zip".to_string(), JsonValue::Null),
+                ])),
+            ),
+            (
+                "deliveryOptions".to_string(),
+                JsonValue::Array(vec![JsonValue::Object(BTreeMap::from([
+                    ("handle".to_string(), string("standard")),
+                    ("title".to_string(), string("Standard")),
+                    ("deliveryMethodType".to_string(), string("SHIPPING")),
+                    (
+                        "cost".to_string(),
+                        JsonValue::Object(BTreeMap::from([
+                            ("amount".to_string(), string("7.5")),
+                            ("currencyCode".to_string(), string("CAD")),
+                        ])),
+                    ),
+                ]))]),
+            ),
+            (
+                "selectedDeliveryOptionHandle".to_string(),
+                string("standard"),
+            ),
+        ]))]);
+
+        match json_body {
+            JsonValue::Object(body) => {
+                assert_eq!(body.get("deliveryGroups"), Some(&expected_delivery_groups));
+            }
+            _ => panic!("Expected a JSON object"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn runs_fixtures() {
+        run_fixtures(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/cart_delivery_options_discounts_generate_fetch"
+            ),
+            cart_delivery_options_discounts_generate_fetch,
+        );
+    }
 }
@@ -1,58 +1,596 @@
 // [START discount-function.delivery.run]
-use super::schema;
+use super::discount_code_rejection::EnteredDiscountCodesReject;
+use super::fetch_response::{known_selection_strategy, response_operations, FetchResponse};
+use super::network_policy::{NetworkPolicy, RemoteOperations};
+use super::schema::cart_delivery_options_discounts_generate_run as query;
+use discount_core::explain::explain;
+use discount_core::money::{Money, Percentage};
+use discount_core::operations::{delivery_discounts, delivery_group_percentage_discount};
+use discount_core::{schema, targets, DiscountClasses, DiscountError};
 use shopify_function::prelude::*;
 use shopify_function::Result;
 #[derive(Deserialize)]
 #[shopify_function(rename_all = "camelCase")]
 pub struct OperationItem {
     delivery_discounts_add: Option<schema::DeliveryDiscountsAddOperation>,
+    delivery_option_decisions: Option<Vec<DeliveryOptionDecision>>,
     entered_discount_codes_accept: Option<schema::EnteredDiscountCodesAcceptOperation>,
+    entered_discount_codes_reject: Option<EnteredDiscountCodesReject>,
+    veto_local_discounts: Option<bool>,
 }
-pub type JsonBody = Vec<OperationItem>;
+pub type JsonBody = FetchResponse<OperationItem>;
+
+/// A discount decided by the server for a single delivery option, or for a
+/// whole delivery group when no handle is given.
+#[derive(Deserialize)]
+#[shopify_function(rename_all = "camelCase")]
+pub struct DeliveryOptionDecision {
+    delivery_group_id: String,
+    delivery_option_handle: Option<String>,
+    percentage: Option<Decimal>,
+    fixed_amount: Option<Decimal>,
+    message: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[shopify_function(rename_all = "camelCase")]
+pub struct DiscountConfiguration {
+    #[shopify_function(default)]
+    delivery_percentage: Percentage,
+    #[shopify_function(default)]
+    network_policy: NetworkPolicy,
+}
+
+const TARGET: &str = "cart_delivery_options_discounts_generate_run";
 
 #[shopify_function]
-fn cart_delivery_options_discounts_generate_run(
-    input: schema::cart_delivery_options_discounts_generate_run::Input,
+pub(crate) fn cart_delivery_options_discounts_generate_run(
+    input: query::Input,
 ) -> Result<schema::CartDeliveryOptionsDiscountsGenerateRunResult> {
     // [START discount-function.delivery.run.body]
-    let fetch_result = input.fetch_result().ok_or("Missing fetch result")?;
-    let discount_classes = &input.discount().discount_classes();
-
-    // Check if shipping discount class is set
-    let has_shipping_discount_class = discount_classes.contains(&schema::DiscountClass::Shipping);
+    let discount_classes = DiscountClasses::new(input.discount().discount_classes());
+    explain!("discount classes: {discount_classes}");
 
     // If shipping discount class is not set, return empty operations
-    if !has_shipping_discount_class {
-        return Ok(schema::CartDeliveryOptionsDiscountsGenerateRunResult { operations: vec![] });
+    if !discount_classes.shipping {
+        explain!("no SHIPPING discount class, no operations");
+        return Ok(schema::CartDeliveryOptionsDiscountsGenerateRunResult::empty());
     }
 
-    // Use jsonBody which is the only available property
-    let operation_items = fetch_result
-        .json_body()
-        .ok_or("Missing json_body in response")?;
+    // A discount without a metafield has no local rules and merges the server response
+    let default_configuration = DiscountConfiguration::default();
+    let discount_configuration = match input.discount().metafield() {
+        Some(metafield) => {
+            let configuration = metafield.json_value();
+            explain!(
+                "metafield parsed: deliveryPercentage {}, networkPolicy {:?}",
+                configuration.delivery_percentage,
+                configuration.network_policy
+            );
+            configuration
+        }
+        None => {
+            explain!("no metafield, no local discounts");
+            &default_configuration
+        }
+    };
 
-    // Convert the response into operations
-    let mut operations = Vec::new();
+    let local_operations = local_operations(&input, discount_configuration);
+
+    // The fetch target may skip the request, the request may fail, or the server
+    // may return a malformed response, in which case only the local operations
+    // are applied
+    let remote_operations = match input.fetch_result() {
+        None => {
+            explain!("no fetch result, the fetch target skipped the request");
+            None
+        }
+        Some(fetch_result) => {
+            match response_operations(*fetch_result.status(), fetch_result.json_body()) {
+                Ok(operation_items) => {
+                    explain!("server returned {} operation items", operation_items.len());
+                    Some(remote_operations(&input, operation_items))
+                }
+                Err(kind) => {
+                    let error = DiscountError::new(TARGET, kind);
+                    explain!("{}", error.details());
+                    error.handle(None)?
+                }
+            }
+        }
+    };
+
+    let operations = discount_configuration
+        .network_policy
+        .apply(local_operations, remote_operations);
+
+    Ok(schema::CartDeliveryOptionsDiscountsGenerateRunResult { operations })
+    // [END discount-function.delivery.run.body]
+}
+
+// [START discount-function.delivery.run.local]
+fn local_operations(
+    input: &query::Input,
+    discount_configuration: &DiscountConfiguration,
+) -> Vec<schema::DeliveryOperation> {
+    let Some(first_delivery_group) = input.cart().delivery_groups().first() else {
+        explain!("local delivery discount skipped: no delivery groups in the cart");
+        return vec![];
+    };
+
+    let delivery_percentage = discount_configuration.delivery_percentage.capped();
+    if !delivery_percentage.is_positive() {
+        explain!("local delivery discount skipped: deliveryPercentage is {delivery_percentage}");
+        return vec![];
+    }
+    explain!(
+        "local delivery discount: {delivery_percentage}% off delivery group {:?}",
+        first_delivery_group.id()
+    );
+
+    vec![delivery_group_percentage_discount(
+        first_delivery_group.id(),
+        delivery_percentage,
+        format!("{}% OFF DELIVERY", delivery_percentage),
+    )]
+}
+// [END discount-function.delivery.run.local]
+
+// [START discount-function.delivery.run.remote]
+fn remote_operations(
+    input: &query::Input,
+    operation_items: &[OperationItem],
+) -> RemoteOperations<schema::DeliveryOperation> {
+    let mut remote = RemoteOperations {
+        discount_code_operations: vec![],
+        discount_operations: vec![],
+        veto_local_discounts: false,
+    };
 
     // Process each operation item
     for item in operation_items {
         // Always include discount code operations
         if let Some(validations) = &item.entered_discount_codes_accept {
-            operations.push(schema::DeliveryOperation::EnteredDiscountCodesAccept(
-                validations.clone(),
-            ));
+            remote.discount_code_operations.push(
+                schema::DeliveryOperation::EnteredDiscountCodesAccept(validations.clone()),
+            );
+        }
+
+        // Rejected codes have no operation, log why they were rejected instead
+        if let Some(rejections) = &item.entered_discount_codes_reject {
+            rejections.log();
         }
 
         // Include delivery discounts (shipping discount class is already verified)
-        if let Some(delivery_discounts_add_operation) = &item.delivery_discounts_add {
-            operations.push(schema::DeliveryOperation::DeliveryDiscountsAdd(
-                delivery_discounts_add_operation.clone(),
-            ));
+        if let Some(delivery_discounts_add_operation) = item
+            .delivery_discounts_add
+            .as_ref()
+            .filter(|operation| known_selection_strategy(&operation.selection_strategy))
+        {
+            remote
+                .discount_operations
+                .push(schema::DeliveryOperation::DeliveryDiscountsAdd(
+                    delivery_discounts_add_operation.clone(),
+                ));
+        }
+
+        // Map the per-option decisions onto delivery option and delivery group targets
+        if let Some(decisions) = &item.delivery_option_decisions {
+            let candidates: Vec<schema::DeliveryDiscountCandidate> = decisions
+                .iter()
+                .filter_map(|decision| delivery_option_candidate(input, decision))
+                .collect();
+            if !candidates.is_empty() {
+                remote
+                    .discount_operations
+                    .push(delivery_discounts(candidates));
+            }
         }
         // Ignore cart/order discounts for delivery operations
+
+        if item.veto_local_discounts == Some(true) {
+            remote.veto_local_discounts = true;
+        }
     }
 
-    Ok(schema::CartDeliveryOptionsDiscountsGenerateRunResult { operations })
-    // [END discount-function.delivery.run.body]
+    remote
 }
+
+// Decisions for delivery groups or options that aren't in the cart, or without a
+// single positive value, are skipped. Percentages are capped at 100, like the
+// local discount's.
+fn delivery_option_candidate(
+    input: &query::Input,
+    decision: &DeliveryOptionDecision,
+) -> Option<schema::DeliveryDiscountCandidate> {
+    let Some(delivery_group) = input
+        .cart()
+        .delivery_groups()
+        .iter()
+        .find(|delivery_group| *delivery_group.id() == decision.delivery_group_id)
+    else {
+        explain!(
+            "server's delivery decision skipped: no delivery group {:?} in the cart",
+            decision.delivery_group_id
+        );
+        return None;
+    };
+
+    let target = match &decision.delivery_option_handle {
+        Some(handle) => {
+            if !delivery_group
+                .delivery_options()
+                .iter()
+                .any(|delivery_option| delivery_option.handle() == handle)
+            {
+                explain!("server's delivery decision skipped: no delivery option {handle:?}");
+                return None;
+            }
+            targets::delivery_option(handle)
+        }
+        None => targets::delivery_group(delivery_group.id()),
+    };
+
+    let value = match (decision.percentage, decision.fixed_amount) {
+        (Some(_), Some(_)) => {
+            explain!("server's delivery decision skipped: both a percentage and a fixedAmount");
+            return None;
+        }
+        (Some(percentage), None) => {
+            let Some(percentage) = Percentage::from_decimal(percentage)
+                .map(Percentage::capped)
+                .filter(|percentage| percentage.is_positive())
+            else {
+                explain!("server's delivery decision skipped: percentage is {percentage}");
+                return None;
+            };
+            schema::DeliveryDiscountCandidateValue::Percentage(schema::Percentage {
+                value: percentage.to_decimal(),
+            })
+        }
+        (None, Some(amount)) => {
+            let Some(amount) = Money::from_decimal(amount).filter(|amount| amount.is_positive())
+            else {
+                explain!("server's delivery decision skipped: fixedAmount is {amount}");
+                return None;
+            };
+            schema::DeliveryDiscountCandidateValue::FixedAmount(schema::FixedAmount {
+                amount: amount.to_decimal(),
+            })
+        }
+        (None, None) => {
+            explain!("server's delivery decision skipped: no percentage or fixedAmount");
+            return None;
+        }
+    };
+
+    Some(schema::DeliveryDiscountCandidate {
+        targets: vec![target],
+        value,
+        message: decision.message.clone(),
+        associated_discount_code: None,
+    })
+}
+// [END discount-function.delivery.run.remote]
 // [END discount-function.delivery.run]
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use discount_function_devtools::cart_builder::CartBuilder;
+    use discount_function_devtools::fixtures::run_fixtures;
+    use discount_function_devtools::fuzz::{arbitrary_json, corrupted, deeply_nested};
+    use discount_function_devtools::properties::{
+        check_run_invariants, configured_percentage, delivery_groups, discount_classes,
+    };
+    use discount_function_devtools::run_to_json;
+    use proptest::prelude::*;
+    use serde_json::json;
+    use shopify_function::run_function_with_input;
+
+    #[test]
+    fn maps_delivery_option_decisions_to_targets() -> Result<()> {
+        let input = json!({
+            "cart": {
+                "buyerIdentity": null,
+                "deliveryGroups": [
+                    {
+                        "id": "gid://shopify/CartDeliveryGroup/0",
+                        "deliveryOptions": [
+                            {"handle": "standard"},
+                            {"handle": "express"}
+                        ]
+                    }
+                ]
+            },
+            "discount": {
+                "discountClasses": ["SHIPPING"],
+                "metafield": null
+            },
+            "fetchResult": {
+                "status": 200,
+                "jsonBody": [
+                    {
+                        "deliveryOptionDecisions": [
+                            {
+                                "deliveryGroupId": "gid://shopify/CartDeliveryGroup/0",
+                                "deliveryOptionHandle": "standard",
+                                "percentage": "100",
+                                "message": "FREE STANDARD SHIPPING"
+                            },
+                            {
+                                "deliveryGroupId": "gid://shopify/CartDeliveryGroup/0",
+                                "fixedAmount": "5.0"
+                            },
+                            {
+                                "deliveryGroupId": "gid://shopify/CartDeliveryGroup/0",
+                                "deliveryOptionHandle": "overnight",
+                                "percentage": "50"
+                            },
+                            {
+                                "deliveryGroupId": "gid://shopify/CartDeliveryGroup/1",
+                                "percentage": "50"
+                            }
+                        ]
+                    }
+                ]
+            }
+        })
+        .to_string();
+
+        let result = run_function_with_input(cart_delivery_options_discounts_generate_run, &input)?;
+
+        // Decisions for unknown delivery options or groups are ignored
+        let expected = vec![schema::DeliveryOperation::DeliveryDiscountsAdd(
+            schema::DeliveryDiscountsAddOperation {
+                selection_strategy: schema::DeliveryDiscountSelectionStrategy::All,
+                candidates: vec![
+                    schema::DeliveryDiscountCandidate {
+                        targets: vec![schema::DeliveryDiscountCandidateTarget::DeliveryOption(
+                            schema::DeliveryOptionTarget {
+                                handle: "standard".to_string(),
+                            },
+                        )],
+                        value: schema::DeliveryDiscountCandidateValue::Percentage(
+                            schema::Percentage {
+                                value: Decimal(100.0),
+                            },
+                        ),
+                        message: Some("FREE STANDARD SHIPPING".to_string()),
+                        associated_discount_code: None,
+                    },
+                    schema::DeliveryDiscountCandidate {
+                        targets: vec![schema::DeliveryDiscountCandidateTarget::DeliveryGroup(
+                            schema::DeliveryGroupTarget {
+                                id: "gid://shopify/CartDeliveryGroup/0".to_string(),
+                            },
+                        )],
+                        value: schema::DeliveryDiscountCandidateValue::FixedAmount(
+                            schema::FixedAmount {
+                                amount: Decimal(5.0),
+                            },
+                        ),
+                        message: None,
+                        associated_discount_code: None,
+                    },
+                ],
+            },
+        )];
+        assert_eq!(result.operations, expected);
+        Ok(())
+    }
+
+    /// Keys of the operations in a response, so that fuzzed bodies reach the
+    /// fields of each operation.
+    const OPERATION_KEYS: &[&str] = &[
+        "deliveryDiscountsAdd",
+        "deliveryOptionDecisions",
+        "enteredDiscountCodesAccept",
+        "enteredDiscountCodesReject",
+        "vetoLocalDiscounts",
+        "deliveryGroupId",
+        "deliveryOptionHandle",
+        "percentage",
+        "fixedAmount",
+        "message",
+        "selectionStrategy",
+        "candidates",
+        "targets",
+        "deliveryGroup",
+        "deliveryOption",
+        "id",
+        "handle",
+        "value",
+        "amount",
+        "codes",
+        "code",
+        "reason",
+    ];
+
+    fn shipping_input() -> CartBuilder {
+        CartBuilder::new()
+            .delivery_group("gid://shopify/CartDeliveryGroup/0")
+            .delivery_option("standard", 5.0)
+            .delivery_option("express", 15.0)
+            .discount_classes(["SHIPPING"])
+            .metafield(json!({ "deliveryPercentage": 10, "networkPolicy": "REPLACE" }))
+    }
+
+    fn free_standard_shipping() -> serde_json::Value {
+        json!({
+            "deliveryOptionDecisions": [
+                {
+                    "deliveryGroupId": "gid://shopify/CartDeliveryGroup/0",
+                    "deliveryOptionHandle": "standard",
+                    "percentage": "100"
+                }
+            ]
+        })
+    }
+
+    #[test]
+    fn applies_local_discounts_when_response_is_malformed() -> Result<()> {
+        let local_operations = run_function_with_input(
+            cart_delivery_options_discounts_generate_run,
+            &shipping_input().build(),
+        )?
+        .operations;
+        assert_eq!(local_operations.len(), 1);
+
+        for json_body in [
+            json!("[]"),
+            json!([{ "deliveryOptionDecisions": [{ "percentage": "100" }] }]),
+            json!([free_standard_shipping(), 42]),
+            deeply_nested(100),
+        ] {
+            let input = shipping_input().fetch_result(200, json_body).build();
+
+            let result =
+                run_function_with_input(cart_delivery_options_discounts_generate_run, &input)?;
+
+            assert_eq!(result.operations, local_operations);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn validates_delivery_option_decisions() -> Result<()> {
+        let decision = |value: serde_json::Value| {
+            let mut decision = json!({
+                "deliveryGroupId": "gid://shopify/CartDeliveryGroup/0",
+                "deliveryOptionHandle": "standard"
+            });
+            decision
+                .as_object_mut()
+                .unwrap()
+                .extend(value.as_object().unwrap().clone());
+            decision
+        };
+        let json_body = json!([{
+            "deliveryOptionDecisions": [
+                decision(json!({ "percentage": "150" })),
+                decision(json!({ "percentage": "0" })),
+                decision(json!({ "fixedAmount": "-5.0" })),
+                decision(json!({ "percentage": "10", "fixedAmount": "5.0" })),
+            ]
+        }]);
+        let input = shipping_input().fetch_result(200, json_body).build();
+
+        let result = run_function_with_input(cart_delivery_options_discounts_generate_run, &input)?;
+
+        // 150% is capped at 100%, and the other decisions are skipped
+        let expected = vec![delivery_discounts(vec![
+            schema::DeliveryDiscountCandidate {
+                targets: vec![targets::delivery_option("standard")],
+                value: schema::DeliveryDiscountCandidateValue::Percentage(schema::Percentage {
+                    value: Decimal(100.0),
+                }),
+                message: None,
+                associated_discount_code: None,
+            },
+        ])];
+        assert_eq!(result.operations, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn handles_large_responses() -> Result<()> {
+        let json_body = vec![free_standard_shipping(); 20_000];
+        let input = shipping_input().fetch_result(200, json!(json_body)).build();
+
+        let result = run_function_with_input(cart_delivery_options_discounts_generate_run, &input)?;
+
+        assert_eq!(result.operations.len(), 20_000);
+        Ok(())
+    }
+
+    #[test]
+    fn runs_fixtures() {
+        run_fixtures(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/cart_delivery_options_discounts_generate_run"
+            ),
+            cart_delivery_options_discounts_generate_run,
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn satisfies_run_invariants(
+            delivery_groups in delivery_groups(),
+            discount_classes in discount_classes(),
+            delivery_percentage in configured_percentage(),
+            decisions in prop::collection::vec(
+                (0..4usize, prop::option::of(0..5usize), configured_percentage()),
+                0..6,
+            ),
+        ) {
+            // Decisions for any delivery group and option the generated
+            // groups may have, and some they don't
+            let decisions: Vec<serde_json::Value> = decisions
+                .into_iter()
+                .map(|(group_index, option_index, percentage)| {
+                    json!({
+                        "deliveryGroupId": format!("gid://shopify/CartDeliveryGroup/{group_index}"),
+                        "deliveryOptionHandle": option_index.map(|index| format!("option-{index}")),
+                        "percentage": percentage.to_string()
+                    })
+                })
+                .collect();
+            let input = json!({
+                "cart": { "deliveryGroups": delivery_groups },
+                "discount": {
+                    "discountClasses": discount_classes,
+                    "metafield": {
+                        "jsonValue": { "deliveryPercentage": delivery_percentage }
+                    }
+                },
+                "fetchResult": {
+                    "status": 200,
+                    "jsonBody": [{ "deliveryOptionDecisions": decisions }]
+                }
+            });
+            let output = run_to_json(cart_delivery_options_discounts_generate_run, &input.to_string());
+
+            check_run_invariants(&input, &output)?;
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn handles_arbitrary_responses(json_body in arbitrary_json(OPERATION_KEYS)) {
+            let input = shipping_input().fetch_result(200, json_body).to_json();
+            let output =
+                run_to_json(cart_delivery_options_discounts_generate_run, &input.to_string());
+
+            prop_assert!(output["operations"].is_array(), "{output}");
+        }
+
+        #[test]
+        fn handles_corrupted_responses(
+            json_body in corrupted(
+                vec![
+                    json!([free_standard_shipping()]),
+                    json!([{ "enteredDiscountCodesAccept": { "codes": [{ "code": "SHIPFREE" }] } }]),
+                    json!([{
+                        "deliveryDiscountsAdd": {
+                            "selectionStrategy": "ALL",
+                            "candidates": [{
+                                "value": { "percentage": { "value": "50" } },
+                                "targets": [{ "deliveryGroup": { "id": "gid://shopify/CartDeliveryGroup/0" } }]
+                            }]
+                        }
+                    }, { "vetoLocalDiscounts": true }]),
+                ],
+                OPERATION_KEYS,
+            )
+        ) {
+            let input = shipping_input().fetch_result(200, json_body).to_json();
+            let output =
+                run_to_json(cart_delivery_options_discounts_generate_run, &input.to_string());
+
+            prop_assert!(output["operations"].is_array(), "{output}");
+        }
+    }
+}
@@ -1,14 +1,44 @@
 // [START discount-function.cart.fetch]
-use super::schema;
+use super::fetch_policy::{FetchConditions, FetchConfiguration};
+use super::request_fingerprint::{request_headers, RequestFingerprint};
+use super::schema::cart_lines_discounts_generate_fetch as query;
+use discount_core::money::Money;
+use discount_core::schema;
 use shopify_function;
 use shopify_function::prelude::*;
 use std::collections::BTreeMap;
 
 #[shopify_function]
-fn cart_lines_discounts_generate_fetch(
-    input: schema::cart_lines_discounts_generate_fetch::Input,
+pub(crate) fn cart_lines_discounts_generate_fetch(
+    input: query::Input,
 ) -> shopify_function::Result<schema::CartLinesDiscountsGenerateFetchResult> {
     let entered_discount_codes = &input.entered_discount_codes();
+
+    // [START discount-function.cart.fetch.policy]
+    let default_configuration = FetchConfiguration::default();
+    let fetch_configuration = match input.discount().metafield() {
+        Some(metafield) => metafield.json_value(),
+        None => &default_configuration,
+    };
+
+    let fetch_conditions = FetchConditions {
+        has_discount_codes: !entered_discount_codes.is_empty(),
+        subtotal: Money::from_decimal(*input.cart().cost().subtotal_amount().amount()),
+        is_authenticated_buyer: input
+            .cart()
+            .buyer_identity()
+            .is_some_and(|buyer_identity| *buyer_identity.is_authenticated()),
+    };
+
+    // Skip the network call when the server response can't change the outcome
+    if !fetch_configuration
+        .fetch_policy
+        .should_fetch(&fetch_conditions)
+    {
+        return Ok(schema::CartLinesDiscountsGenerateFetchResult { request: None });
+    }
+    // [END discount-function.cart.fetch.policy]
+
     let json_body = JsonValue::Object(BTreeMap::from([(
         "enteredDiscountCodes".to_string(),
         JsonValue::Array(
@@ -19,17 +49,28 @@ fn cart_lines_discounts_generate_fetch(
         ),
     )]));
 
+    // [START discount-function.cart.fetch.headers]
+    // Shopify's amounts are always numbers
+    let mut fingerprint = RequestFingerprint::default();
+    for line in input.cart().lines() {
+        fingerprint.cart_line(
+            line.id(),
+            *line.quantity(),
+            Money::from_decimal(*line.cost().subtotal_amount().amount()).unwrap_or_default(),
+        );
+    }
+    for code in entered_discount_codes.iter() {
+        fingerprint.discount_code(code);
+    }
+    let correlation_id = input
+        .cart()
+        .attribute()
+        .and_then(|attribute| attribute.value().cloned());
+    let headers = request_headers(&mut fingerprint, correlation_id);
+    // [END discount-function.cart.fetch.headers]
+
     let request = schema::HttpRequest {
-        headers: vec![
-            schema::HttpRequestHeader {
-                name: "accept".to_string(),
-                value: "application/json".to_string(),
-            },
-            schema::HttpRequestHeader {
-                name: "Content-Type".to_string(),
-                value: "application/json".to_string(),
-            },
-        ],
+        headers,
         method: schema::HttpRequestMethod::Post,
         policy: schema::HttpRequestPolicy {
             read_timeout_ms: 2000,
@@ -50,6 +91,9 @@ fn cart_lines_discounts_generate_fetch(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::request_fingerprint::{CORRELATION_ID_HEADER, FINGERPRINT_HEADER};
+    use discount_function_devtools::cart_builder::CartBuilder;
+    use discount_function_devtools::fixtures::run_fixtures;
     use serde_json::json;
     use shopify_function::run_function_with_input;
 
@@ -58,7 +102,17 @@ mod tests {
         let input = json!({
             "enteredDiscountCodes": [],
             "cart": {
-                "lines": []
+                "attribute": null,
+                "lines": [],
+                "buyerIdentity": null,
+                "cost": {
+                    "subtotalAmount": {
+                        "amount": "0.0"
+                    }
+                }
+            },
+            "discount": {
+                "metafield": null
             }
         })
         .to_string();
@@ -79,6 +133,10 @@ mod tests {
                         name: "Content-Type".to_string(),
                         value: "application/json".to_string(),
                     },
+                    schema::HttpRequestHeader {
+                        name: FINGERPRINT_HEADER.to_string(),
+                        value: "cbf29ce484222325".to_string(),
+                    },
                 ],
                 method: schema::HttpRequestMethod::Post,
                 policy: schema::HttpRequestPolicy {
@@ -93,4 +151,131 @@ mod tests {
         assert_eq!(result, expected);
         Ok(())
     }
+
+    fn fetch_input(
+        entered_discount_codes: &[&str],
+        subtotal: f64,
+        is_authenticated: bool,
+        fetch_policy: serde_json::Value,
+    ) -> String {
+        CartBuilder::new()
+            .line("gid://shopify/CartLine/0", subtotal)
+            .entered_discount_codes(entered_discount_codes.iter().copied())
+            .buyer("buyer@example.com", is_authenticated)
+            .metafield(json!({ "fetchPolicy": fetch_policy }))
+            .build()
+    }
+
+    #[test]
+    fn skips_request_without_entered_discount_codes() -> shopify_function::Result<()> {
+        let input = fetch_input(&[], 100.0, true, json!({ "requireDiscountCodes": true }));
+
+        let result = run_function_with_input(cart_lines_discounts_generate_fetch, &input)?;
+
+        assert_eq!(result.request, None);
+        Ok(())
+    }
+
+    #[test]
+    fn skips_request_below_minimum_subtotal() -> shopify_function::Result<()> {
+        let input = fetch_input(&["SAVE20"], 49.99, true, json!({ "minimumSubtotal": 50 }));
+
+        let result = run_function_with_input(cart_lines_discounts_generate_fetch, &input)?;
+
+        assert_eq!(result.request, None);
+        Ok(())
+    }
+
+    #[test]
+    fn skips_request_for_anonymous_buyers() -> shopify_function::Result<()> {
+        let input = fetch_input(
+            &["SAVE20"],
+            100.0,
+            false,
+            json!({ "requireAuthenticatedBuyer": true }),
+        );
+
+        let result = run_function_with_input(cart_lines_discounts_generate_fetch, &input)?;
+
+        assert_eq!(result.request, None);
+        Ok(())
+    }
+
+    #[test]
+    fn sends_request_when_all_conditions_are_met() -> shopify_function::Result<()> {
+        let input = fetch_input(
+            &["SAVE20"],
+            50.0,
+            true,
+            json!({
+                "requireDiscountCodes": true,
+                "minimumSubtotal": 50,
+                "requireAuthenticatedBuyer": true
+            }),
+        );
+
+        let result = run_function_with_input(cart_lines_discounts_generate_fetch, &input)?;
+
+        assert!(result.request.is_some());
+        Ok(())
+    }
+
+    fn fingerprint_header(input: &str) -> shopify_function::Result<String> {
+        let result = run_function_with_input(cart_lines_discounts_generate_fetch, input)?;
+        let headers = result.request.expect("Expected a request").headers;
+        Ok(headers
+            .into_iter()
+            .find(|header| header.name == FINGERPRINT_HEADER)
+            .expect("Expected a fingerprint header")
+            .value)
+    }
+
+    #[test]
+    fn adds_correlation_id_header_from_cart_attribute() -> shopify_function::Result<()> {
+        let input = CartBuilder::new()
+            .line("gid://shopify/CartLine/0", 20.0)
+            .quantity(2)
+            .entered_discount_codes(["SAVE20"])
+            .attribute("checkout-123")
+            .build();
+
+        let result = run_function_with_input(cart_lines_discounts_generate_fetch, &input)?;
+        let headers = result.request.expect("Expected a request").headers;
+
+        assert!(headers.contains(&schema::HttpRequestHeader {
+            name: CORRELATION_ID_HEADER.to_string(),
+            value: "checkout-123".to_string(),
+        }));
+        Ok(())
+    }
+
+    #[test]
+    fn fingerprints_the_cart() -> shopify_function::Result<()> {
+        let input = |quantity| {
+            CartBuilder::new()
+                .line("gid://shopify/CartLine/0", 20.0)
+                .quantity(quantity)
+                .entered_discount_codes(["SAVE20"])
+                .build()
+        };
+
+        let fingerprint = fingerprint_header(&input(2))?;
+
+        // FNV-1a of "code:SAVE20\n" and "line:gid://shopify/CartLine/0:2:20\n"
+        assert_eq!(fingerprint, "8577b4b6639be009");
+        assert_eq!(fingerprint_header(&input(2))?, fingerprint);
+        assert_ne!(fingerprint_header(&input(3))?, fingerprint);
+        Ok(())
+    }
+
+    #[test]
+    fn runs_fixtures() {
+        run_fixtures(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/cart_lines_discounts_generate_fetch"
+            ),
+            cart_lines_discounts_generate_fetch,
+        );
+    }
 }
@@ -1,5 +1,12 @@
 // [START discount-function.cart.run]
-use super::schema;
+use super::discount_code_rejection::EnteredDiscountCodesReject;
+use super::fetch_response::{known_selection_strategy, response_operations, FetchResponse};
+use super::network_policy::{NetworkPolicy, RemoteOperations};
+use super::schema::cart_lines_discounts_generate_run as query;
+use discount_core::explain::{self, explain};
+use discount_core::money::Percentage;
+use discount_core::operations::{order_percentage_discount, product_percentage_discount};
+use discount_core::{schema, targets, DiscountClasses, DiscountError};
 use shopify_function::prelude::*;
 use shopify_function::Result;
 
@@ -9,86 +16,256 @@ pub struct OperationItem {
     product_discounts_add: Option<schema::ProductDiscountsAddOperation>,
     order_discounts_add: Option<schema::OrderDiscountsAddOperation>,
     entered_discount_codes_accept: Option<schema::EnteredDiscountCodesAcceptOperation>,
+    entered_discount_codes_reject: Option<EnteredDiscountCodesReject>,
+    veto_local_discounts: Option<bool>,
 }
-pub type JsonBody = Vec<OperationItem>;
+pub type JsonBody = FetchResponse<OperationItem>;
+
+#[derive(Deserialize, Default)]
+#[shopify_function(rename_all = "camelCase")]
+pub struct DiscountConfiguration {
+    #[shopify_function(default)]
+    cart_line_percentage: Percentage,
+    #[shopify_function(default)]
+    order_percentage: Percentage,
+    #[shopify_function(default)]
+    collection_ids: Vec<String>,
+    #[shopify_function(default)]
+    network_policy: NetworkPolicy,
+}
+
+const TARGET: &str = "cart_lines_discounts_generate_run";
 
 #[shopify_function]
-fn cart_lines_discounts_generate_run(
-    input: schema::cart_lines_discounts_generate_run::Input,
+pub(crate) fn cart_lines_discounts_generate_run(
+    input: query::Input,
 ) -> Result<schema::CartLinesDiscountsGenerateRunResult> {
     // [START discount-function.cart.run.body]
-    let fetch_result = input.fetch_result().ok_or("Missing fetch result")?;
-    let discount_classes = &input.discount().discount_classes();
-
-    // Check if relevant discount classes are set
-    let has_order_discount_class = discount_classes.contains(&schema::DiscountClass::Order);
-    let has_product_discount_class = discount_classes.contains(&schema::DiscountClass::Product);
+    let discount_classes = DiscountClasses::new(input.discount().discount_classes());
+    explain!("discount classes: {discount_classes}");
 
     // If no relevant discount class is set, return empty operations
-    if !has_order_discount_class && !has_product_discount_class {
-        return Ok(schema::CartLinesDiscountsGenerateRunResult { operations: vec![] });
+    if !discount_classes.has_cart_lines_class() {
+        explain!("no PRODUCT or ORDER discount class, no operations");
+        return Ok(schema::CartLinesDiscountsGenerateRunResult::empty());
+    }
+
+    // A discount without a metafield has no local rules and merges the server response
+    let default_configuration = DiscountConfiguration::default();
+    let discount_configuration = match input.discount().metafield() {
+        Some(metafield) => {
+            let configuration = metafield.json_value();
+            explain!(
+                "metafield parsed: cartLinePercentage {}, orderPercentage {}, collectionIds {}, networkPolicy {:?}",
+                configuration.cart_line_percentage,
+                configuration.order_percentage,
+                explain::list(&configuration.collection_ids),
+                configuration.network_policy
+            );
+            configuration
+        }
+        None => {
+            explain!("no metafield, no local discounts");
+            &default_configuration
+        }
+    };
+
+    let local_operations = local_operations(&input, discount_configuration, discount_classes);
+
+    // The fetch target may skip the request, the request may fail, or the server
+    // may return a malformed response, in which case only the local operations
+    // are applied
+    let remote_operations = match input.fetch_result() {
+        None => {
+            explain!("no fetch result, the fetch target skipped the request");
+            None
+        }
+        Some(fetch_result) => {
+            match response_operations(*fetch_result.status(), fetch_result.json_body()) {
+                Ok(operation_items) => {
+                    explain!("server returned {} operation items", operation_items.len());
+                    Some(remote_operations(operation_items, discount_classes))
+                }
+                Err(kind) => {
+                    let error = DiscountError::new(TARGET, kind);
+                    explain!("{}", error.details());
+                    error.handle(None)?
+                }
+            }
+        }
+    };
+
+    let operations = discount_configuration
+        .network_policy
+        .apply(local_operations, remote_operations);
+
+    Ok(schema::CartLinesDiscountsGenerateRunResult { operations })
+    // [END discount-function.cart.run.body]
+}
+
+// [START discount-function.cart.run.local]
+fn local_operations(
+    input: &query::Input,
+    discount_configuration: &DiscountConfiguration,
+    discount_classes: DiscountClasses,
+) -> Vec<schema::CartOperation> {
+    let mut operations = vec![];
+
+    let cart_line_percentage = discount_configuration.cart_line_percentage.capped();
+    let order_percentage = discount_configuration.order_percentage.capped();
+
+    // Add product discounts first if available and allowed
+    if !discount_classes.product {
+        explain!("local product discount skipped: no PRODUCT discount class");
+    } else if !cart_line_percentage.is_positive() {
+        explain!("local product discount skipped: cartLinePercentage is {cart_line_percentage}");
+    } else {
+        let mut cart_line_targets = vec![];
+        let mut skipped_line_ids = vec![];
+        for line in input.cart().lines() {
+            if let query::input::cart::lines::Merchandise::ProductVariant(variant) =
+                &line.merchandise()
+            {
+                if *variant.product().in_any_collection()
+                    || discount_configuration.collection_ids.is_empty()
+                {
+                    cart_line_targets.push(targets::cart_line(line.id()));
+                    continue;
+                }
+            }
+            skipped_line_ids.push(line.id());
+        }
+        explain!(
+            "cart lines without a product in the discount's collections: {}",
+            explain::list(skipped_line_ids)
+        );
+
+        if cart_line_targets.is_empty() {
+            explain!(
+                "local product discount skipped: no cart line is in the discount's collections"
+            );
+        } else {
+            explain!(
+                "local product discount: {cart_line_percentage}% off {} cart lines",
+                cart_line_targets.len()
+            );
+            operations.extend(product_percentage_discount(
+                cart_line_targets,
+                cart_line_percentage,
+                format!("{}% OFF PRODUCT", cart_line_percentage),
+            ));
+        }
     }
 
-    // Use jsonBody which is the only available property
-    let operation_items = fetch_result
-        .json_body()
-        .ok_or("Missing json_body in response")?;
+    // Then add order discounts if available and allowed
+    if !discount_classes.order {
+        explain!("local order discount skipped: no ORDER discount class");
+    } else if !order_percentage.is_positive() {
+        explain!("local order discount skipped: orderPercentage is {order_percentage}");
+    } else {
+        explain!("local order discount: {order_percentage}% off the order subtotal");
+        operations.push(order_percentage_discount(
+            order_percentage,
+            format!("{}% OFF ORDER", order_percentage),
+        ));
+    }
 
-    // Convert the response into operations
-    let mut operations = Vec::new();
+    operations
+}
+// [END discount-function.cart.run.local]
+
+// [START discount-function.cart.run.remote]
+fn remote_operations(
+    operation_items: &[OperationItem],
+    discount_classes: DiscountClasses,
+) -> RemoteOperations<schema::CartOperation> {
+    let mut remote = RemoteOperations {
+        discount_code_operations: vec![],
+        discount_operations: vec![],
+        veto_local_discounts: false,
+    };
 
     // Process each operation item
     for item in operation_items {
         // Always include discount code operations
         if let Some(validations) = &item.entered_discount_codes_accept {
-            operations.push(schema::CartOperation::EnteredDiscountCodesAccept(
-                validations.clone(),
-            ));
+            remote.discount_code_operations.push(
+                schema::CartOperation::EnteredDiscountCodesAccept(validations.clone()),
+            );
+        }
+
+        // Rejected codes have no operation, log why they were rejected instead
+        if let Some(rejections) = &item.entered_discount_codes_reject {
+            rejections.log();
         }
 
         // Include product discounts only if that class is set
-        if has_product_discount_class {
-            if let Some(product_discounts_add_operation) = &item.product_discounts_add {
-                operations.push(schema::CartOperation::ProductDiscountsAdd(
-                    product_discounts_add_operation.clone(),
-                ));
+        if !discount_classes.product && item.product_discounts_add.is_some() {
+            explain!("server's product discount skipped: no PRODUCT discount class");
+        } else if discount_classes.product {
+            if let Some(product_discounts_add_operation) = item
+                .product_discounts_add
+                .as_ref()
+                .filter(|operation| known_selection_strategy(&operation.selection_strategy))
+            {
+                remote
+                    .discount_operations
+                    .push(schema::CartOperation::ProductDiscountsAdd(
+                        product_discounts_add_operation.clone(),
+                    ));
             }
         }
 
         // Include order discounts only if that class is set
-        if has_order_discount_class {
-            if let Some(order_discounts_add_operation) = &item.order_discounts_add {
-                operations.push(schema::CartOperation::OrderDiscountsAdd(
-                    order_discounts_add_operation.clone(),
-                ));
+        if !discount_classes.order && item.order_discounts_add.is_some() {
+            explain!("server's order discount skipped: no ORDER discount class");
+        } else if discount_classes.order {
+            if let Some(order_discounts_add_operation) = item
+                .order_discounts_add
+                .as_ref()
+                .filter(|operation| known_selection_strategy(&operation.selection_strategy))
+            {
+                remote
+                    .discount_operations
+                    .push(schema::CartOperation::OrderDiscountsAdd(
+                        order_discounts_add_operation.clone(),
+                    ));
             }
         }
         // Ignore delivery discounts for cart operations
+
+        if item.veto_local_discounts == Some(true) {
+            remote.veto_local_discounts = true;
+        }
     }
 
-    Ok(schema::CartLinesDiscountsGenerateRunResult { operations })
-    // [END discount-function.cart.run.body]
+    remote
 }
+// [END discount-function.cart.run.remote]
 // [END discount-function.cart.run]
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use discount_function_devtools::assert_snapshot;
+    use discount_function_devtools::cart_builder::CartBuilder;
+    use discount_function_devtools::fixtures::run_fixtures;
+    use discount_function_devtools::fuzz::{arbitrary_json, corrupted, deeply_nested};
+    use discount_function_devtools::properties::{
+        cart_lines, check_run_invariants, configured_percentage, discount_classes,
+    };
+    use discount_function_devtools::run_to_json;
+    use proptest::prelude::*;
     use serde_json::json;
     use shopify_function::run_function_with_input;
 
     #[test]
     fn processes_discount_codes_with_product_and_order_classes() -> Result<()> {
-        let input = json!({
-            "cart": {
-                "lines": []
-            },
-            "discount": {
-                "discountClasses": ["PRODUCT", "ORDER"]
-            },
-            "fetchResult": {
-                "status": 200,
-                "jsonBody": [
+        let input = CartBuilder::new()
+            .discount_classes(["PRODUCT", "ORDER"])
+            .fetch_result(
+                200,
+                json!([
                     {
                         "enteredDiscountCodesAccept": {
                             "codes": [
@@ -138,78 +315,16 @@ mod tests {
                             ]
                         }
                     }
-                ]
-            }
-        })
-        .to_string();
+                ]),
+            )
+            .build();
 
         let result = run_function_with_input(cart_lines_discounts_generate_run, &input)?;
 
-        // We should have 3 operations: discount codes, product discounts, and order discounts
-        assert_eq!(result.operations.len(), 3);
-
-        // First operation should be EnteredDiscountCodesAccept
-        match &result.operations[0] {
-            schema::CartOperation::EnteredDiscountCodesAccept(op) => {
-                assert_eq!(op.codes.len(), 1);
-                assert_eq!(op.codes[0].code, "SUMMER10");
-            }
-            _ => panic!("Expected EnteredDiscountCodesAccept operation"),
-        }
-
-        // Second operation should be ProductDiscountsAdd
-        match &result.operations[1] {
-            schema::CartOperation::ProductDiscountsAdd(op) => {
-                assert_eq!(
-                    op.selection_strategy,
-                    schema::ProductDiscountSelectionStrategy::First
-                );
-                assert_eq!(op.candidates.len(), 1);
-
-                let candidate = &op.candidates[0];
-                match &candidate.value {
-                    schema::ProductDiscountCandidateValue::Percentage(pct) => {
-                        assert_eq!(pct.value, Decimal::from(0.1));
-                    }
-                    _ => panic!("Expected Percentage value"),
-                }
-
-                assert_eq!(candidate.targets.len(), 1);
-                match &candidate.targets[0] {
-                    schema::ProductDiscountCandidateTarget::CartLine(target) => {
-                        assert_eq!(target.id, "gid://shopify/CartLine/123");
-                    }
-                }
-            }
-            _ => panic!("Expected ProductDiscountsAdd operation"),
-        }
-
-        // Third operation should be OrderDiscountsAdd
-        match &result.operations[2] {
-            schema::CartOperation::OrderDiscountsAdd(op) => {
-                assert_eq!(
-                    op.selection_strategy,
-                    schema::OrderDiscountSelectionStrategy::Maximum
-                );
-                assert_eq!(op.candidates.len(), 1);
-
-                let candidate = &op.candidates[0];
-                match &candidate.value {
-                    schema::OrderDiscountCandidateValue::Percentage(pct) => {
-                        assert_eq!(pct.value, Decimal::from(0.15));
-                    }
-                    _ => panic!("Expected Percentage value"),
-                }
-
-                assert_eq!(candidate.targets.len(), 1);
-                match &candidate.targets[0] {
-                    schema::OrderDiscountCandidateTarget::OrderSubtotal(target) => {
-                        assert_eq!(target.excluded_cart_line_ids.len(), 0);
-                    }
-                }
-            }
-            _ => panic!("Expected OrderDiscountsAdd operation"),
-        }
+        assert_snapshot!(
+            "cart_lines_discounts_generate_run/processes_discount_codes_with_product_and_order_classes",
+            &result
+        );
 
         Ok(())
     }
@@ -217,16 +332,11 @@ mod tests {
     #[test]
     fn filters_operations_based_on_discount_classes() -> Result<()> {
         // Only PRODUCT class is enabled, so ORDER discounts should be ignored
-        let input = json!({
-            "cart": {
-                "lines": []
-            },
-            "discount": {
-                "discountClasses": ["PRODUCT"]
-            },
-            "fetchResult": {
-                "status": 200,
-                "jsonBody": [
+        let input = CartBuilder::new()
+            .discount_classes(["PRODUCT"])
+            .fetch_result(
+                200,
+                json!([
                     {
                         "productDiscountsAdd": {
                             "selectionStrategy": "FIRST",
@@ -269,63 +379,29 @@ mod tests {
                             ]
                         }
                     }
-                ]
-            }
-        })
-        .to_string();
+                ]),
+            )
+            .build();
 
         let result = run_function_with_input(cart_lines_discounts_generate_run, &input)?;
 
         // Should only include product discounts and filter out order discounts
-        assert_eq!(
-            result.operations.len(),
-            1,
-            "Should only have one operation (product discounts)"
+        assert_snapshot!(
+            "cart_lines_discounts_generate_run/filters_operations_based_on_discount_classes",
+            &result
         );
 
-        // The operation should be ProductDiscountsAdd
-        match &result.operations[0] {
-            schema::CartOperation::ProductDiscountsAdd(op) => {
-                assert_eq!(
-                    op.selection_strategy,
-                    schema::ProductDiscountSelectionStrategy::First
-                );
-                assert_eq!(op.candidates.len(), 1);
-
-                let candidate = &op.candidates[0];
-                match &candidate.value {
-                    schema::ProductDiscountCandidateValue::Percentage(pct) => {
-                        assert_eq!(pct.value, Decimal::from(0.1));
-                    }
-                    _ => panic!("Expected Percentage value"),
-                }
-
-                assert_eq!(candidate.targets.len(), 1);
-                match &candidate.targets[0] {
-                    schema::ProductDiscountCandidateTarget::CartLine(target) => {
-                        assert_eq!(target.id, "gid://shopify/CartLine/123");
-                    }
-                }
-            }
-            _ => panic!("Expected ProductDiscountsAdd operation"),
-        }
-
         Ok(())
     }
 
     #[test]
     fn returns_empty_operations_with_no_relevant_discount_classes() -> Result<()> {
         // Only DELIVERY class is set, which isn't relevant for cart operations
-        let input = json!({
-            "cart": {
-                "lines": []
-            },
-            "discount": {
-                "discountClasses": ["DELIVERY"]
-            },
-            "fetchResult": {
-                "status": 200,
-                "jsonBody": [
+        let input = CartBuilder::new()
+            .discount_classes(["DELIVERY"])
+            .fetch_result(
+                200,
+                json!([
                     {
                         "productDiscountsAdd": {
                             "selectionStrategy": "FIRST",
@@ -347,10 +423,9 @@ mod tests {
                             ]
                         }
                     }
-                ]
-            }
-        })
-        .to_string();
+                ]),
+            )
+            .build();
 
         let result = run_function_with_input(cart_lines_discounts_generate_run, &input)?;
 
@@ -367,16 +442,11 @@ mod tests {
     #[test]
     fn always_includes_discount_code_operations() -> Result<()> {
         // Only ORDER class is set, but discount code operations should still be included
-        let input = json!({
-            "cart": {
-                "lines": []
-            },
-            "discount": {
-                "discountClasses": ["ORDER"]
-            },
-            "fetchResult": {
-                "status": 200,
-                "jsonBody": [
+        let input = CartBuilder::new()
+            .discount_classes(["ORDER"])
+            .fetch_result(
+                200,
+                json!([
                     {
                         "enteredDiscountCodesAccept": {
                             "codes": [
@@ -405,38 +475,392 @@ mod tests {
                             ]
                         }
                     }
+                ]),
+            )
+            .build();
+
+        let result = run_function_with_input(cart_lines_discounts_generate_run, &input)?;
+
+        // Should only include discount codes and filter out product discounts
+        assert_snapshot!(
+            "cart_lines_discounts_generate_run/always_includes_discount_code_operations",
+            &result
+        );
+
+        Ok(())
+    }
+
+    fn hybrid_input(network_policy: &str) -> CartBuilder {
+        CartBuilder::new()
+            .line("gid://shopify/CartLine/0", 100.0)
+            .discount_classes(["PRODUCT", "ORDER"])
+            .metafield(json!({
+                "cartLinePercentage": 0,
+                "orderPercentage": 10,
+                "collectionIds": [],
+                "networkPolicy": network_policy
+            }))
+    }
+
+    fn local_order_discount() -> schema::CartOperation {
+        schema::CartOperation::OrderDiscountsAdd(schema::OrderDiscountsAddOperation {
+            selection_strategy: schema::OrderDiscountSelectionStrategy::First,
+            candidates: vec![schema::OrderDiscountCandidate {
+                targets: vec![schema::OrderDiscountCandidateTarget::OrderSubtotal(
+                    schema::OrderSubtotalTarget {
+                        excluded_cart_line_ids: vec![],
+                    },
+                )],
+                message: Some("10% OFF ORDER".to_string()),
+                value: schema::OrderDiscountCandidateValue::Percentage(schema::Percentage {
+                    value: Decimal(10.0),
+                }),
+                conditions: None,
+                associated_discount_code: None,
+            }],
+        })
+    }
+
+    fn remote_product_discount() -> serde_json::Value {
+        json!({
+            "productDiscountsAdd": {
+                "selectionStrategy": "FIRST",
+                "candidates": [
+                    {
+                        "value": {
+                            "percentage": {
+                                "value": "20"
+                            }
+                        },
+                        "targets": [
+                            {
+                                "cartLine": {
+                                    "id": "gid://shopify/CartLine/0"
+                                }
+                            }
+                        ]
+                    }
                 ]
             }
         })
-        .to_string();
+    }
+
+    #[test]
+    fn applies_local_discounts_without_fetch_result() -> Result<()> {
+        let input = hybrid_input("MERGE").build();
+
+        let result = run_function_with_input(cart_lines_discounts_generate_run, &input)?;
+
+        assert_eq!(result.operations, vec![local_order_discount()]);
+        Ok(())
+    }
+
+    #[test]
+    fn applies_local_discounts_when_fetch_fails() -> Result<()> {
+        let input = hybrid_input("REPLACE")
+            .fetch_result(500, json!(null))
+            .build();
+
+        let result = run_function_with_input(cart_lines_discounts_generate_run, &input)?;
+
+        assert_eq!(result.operations, vec![local_order_discount()]);
+        Ok(())
+    }
+
+    #[test]
+    fn merges_local_and_remote_discounts() -> Result<()> {
+        let input = hybrid_input("MERGE")
+            .fetch_result(200, json!([remote_product_discount()]))
+            .build();
+
+        let result = run_function_with_input(cart_lines_discounts_generate_run, &input)?;
+
+        assert_eq!(result.operations.len(), 2);
+        assert_eq!(result.operations[0], local_order_discount());
+        assert!(matches!(
+            result.operations[1],
+            schema::CartOperation::ProductDiscountsAdd(_)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn replaces_local_discounts_with_remote_discounts() -> Result<()> {
+        let input = hybrid_input("REPLACE")
+            .fetch_result(200, json!([remote_product_discount()]))
+            .build();
+
+        let result = run_function_with_input(cart_lines_discounts_generate_run, &input)?;
+
+        assert_eq!(result.operations.len(), 1);
+        assert!(matches!(
+            result.operations[0],
+            schema::CartOperation::ProductDiscountsAdd(_)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn vetoes_local_discounts() -> Result<()> {
+        let input = hybrid_input("VETO")
+            .fetch_result(
+                200,
+                json!([
+                    {
+                        "enteredDiscountCodesAccept": {
+                            "codes": [
+                                {"code": "SAVE20"}
+                            ]
+                        }
+                    },
+                    remote_product_discount(),
+                    {
+                        "vetoLocalDiscounts": true
+                    }
+                ]),
+            )
+            .build();
+
+        let result = run_function_with_input(cart_lines_discounts_generate_run, &input)?;
+
+        // Only the discount code operation remains, remote discounts are never applied
+        assert_eq!(result.operations.len(), 1);
+        assert!(matches!(
+            result.operations[0],
+            schema::CartOperation::EnteredDiscountCodesAccept(_)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_local_discounts_when_not_vetoed() -> Result<()> {
+        let input = hybrid_input("VETO")
+            .fetch_result(200, json!([remote_product_discount()]))
+            .build();
+
+        let result = run_function_with_input(cart_lines_discounts_generate_run, &input)?;
+
+        assert_eq!(result.operations, vec![local_order_discount()]);
+        Ok(())
+    }
+
+    #[test]
+    fn does_not_add_operations_for_rejected_discount_codes() -> Result<()> {
+        let input = CartBuilder::new()
+            .discount_classes(["ORDER"])
+            .fetch_result(
+                200,
+                json!([
+                    {
+                        "enteredDiscountCodesAccept": {
+                            "codes": [
+                                {"code": "SAVE20"}
+                            ]
+                        }
+                    },
+                    {
+                        "enteredDiscountCodesReject": {
+                            "codes": [
+                                {
+                                    "code": "SUMMER10",
+                                    "reason": "EXPIRED",
+                                    "message": "This code expired on August 31"
+                                },
+                                {
+                                    "code": "VIP",
+                                    "reason": "CUSTOMER_NOT_ELIGIBLE"
+                                }
+                            ]
+                        }
+                    }
+                ]),
+            )
+            .build();
 
         let result = run_function_with_input(cart_lines_discounts_generate_run, &input)?;
 
-        // Should only include discount codes and filter out product discounts
         assert_eq!(
-            result.operations.len(),
-            1,
-            "Should only have one operation (discount codes)"
+            result.operations,
+            vec![schema::CartOperation::EnteredDiscountCodesAccept(
+                schema::EnteredDiscountCodesAcceptOperation {
+                    codes: vec![schema::DiscountCode {
+                        code: "SAVE20".to_string(),
+                    }],
+                },
+            )]
         );
+        Ok(())
+    }
 
-        // The operation should be EnteredDiscountCodesAccept
-        match &result.operations[0] {
-            schema::CartOperation::EnteredDiscountCodesAccept(op) => {
-                assert_eq!(op.codes.len(), 1);
-                assert_eq!(op.codes[0].code, "SAVE20");
-            }
-            _ => panic!("Expected EnteredDiscountCodesAccept operation"),
-        }
+    /// Keys of the operations in a response, so that fuzzed bodies reach the
+    /// fields of each operation.
+    const OPERATION_KEYS: &[&str] = &[
+        "productDiscountsAdd",
+        "orderDiscountsAdd",
+        "enteredDiscountCodesAccept",
+        "enteredDiscountCodesReject",
+        "vetoLocalDiscounts",
+        "selectionStrategy",
+        "candidates",
+        "targets",
+        "cartLine",
+        "orderSubtotal",
+        "excludedCartLineIds",
+        "id",
+        "value",
+        "percentage",
+        "fixedAmount",
+        "amount",
+        "message",
+        "codes",
+        "code",
+        "reason",
+    ];
+
+    #[test]
+    fn explains_why_server_discounts_were_ignored() -> Result<()> {
+        let input = hybrid_input("REPLACE")
+            .fetch_result(200, json!({ "operations": [] }))
+            .build();
+        explain::take_trace();
+
+        run_function_with_input(cart_lines_discounts_generate_run, &input)?;
 
-        // Make sure we don't have any product discount operations
-        assert!(
-            !result
-                .operations
-                .iter()
-                .any(|op| matches!(op, schema::CartOperation::ProductDiscountsAdd(_))),
-            "Should not have ProductDiscountsAdd operations when PRODUCT class is not set"
+        assert_eq!(
+            explain::take_trace(),
+            vec![
+                "discount classes: ORDER, PRODUCT",
+                "metafield parsed: cartLinePercentage 0, orderPercentage 10, collectionIds none, networkPolicy Replace",
+                "local product discount skipped: cartLinePercentage is 0",
+                "local order discount: 10% off the order subtotal",
+                "MALFORMED_FETCH_RESPONSE in cart_lines_discounts_generate_run at fetchResult.jsonBody: Malformed response: Invalid type",
+                "no usable response from the server, applying only local discounts",
+            ]
         );
+        Ok(())
+    }
+
+    #[test]
+    fn applies_local_discounts_when_response_is_malformed() -> Result<()> {
+        for json_body in [
+            json!({ "operations": [] }),
+            json!([{ "productDiscountsAdd": { "selectionStrategy": "ALL" } }]),
+            json!([remote_product_discount(), "not an operation"]),
+            deeply_nested(100),
+        ] {
+            let input = hybrid_input("REPLACE").fetch_result(200, json_body).build();
+
+            let result = run_function_with_input(cart_lines_discounts_generate_run, &input)?;
+
+            assert_eq!(result.operations, vec![local_order_discount()]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn ignores_remote_discounts_with_unknown_selection_strategy() -> Result<()> {
+        let mut remote_discount = remote_product_discount();
+        remote_discount["productDiscountsAdd"]["selectionStrategy"] = json!("first");
+        let input = hybrid_input("MERGE")
+            .fetch_result(200, json!([remote_discount]))
+            .build();
+
+        let result = run_function_with_input(cart_lines_discounts_generate_run, &input)?;
+
+        assert_eq!(result.operations, vec![local_order_discount()]);
+        Ok(())
+    }
+
+    #[test]
+    fn handles_large_responses() -> Result<()> {
+        let json_body = vec![remote_product_discount(); 20_000];
+        let input = hybrid_input("MERGE")
+            .fetch_result(200, json!(json_body))
+            .build();
+
+        let result = run_function_with_input(cart_lines_discounts_generate_run, &input)?;
 
+        assert_eq!(result.operations.len(), 20_001);
         Ok(())
     }
+
+    #[test]
+    fn runs_fixtures() {
+        run_fixtures(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/cart_lines_discounts_generate_run"
+            ),
+            cart_lines_discounts_generate_run,
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn satisfies_run_invariants(
+            lines in cart_lines(),
+            discount_classes in discount_classes(),
+            cart_line_percentage in configured_percentage(),
+            order_percentage in configured_percentage(),
+            network_policy in prop_oneof![Just("MERGE"), Just("REPLACE"), Just("VETO")],
+        ) {
+            let input = json!({
+                "cart": { "lines": lines },
+                "discount": {
+                    "discountClasses": discount_classes,
+                    "metafield": {
+                        "jsonValue": {
+                            "cartLinePercentage": cart_line_percentage,
+                            "orderPercentage": order_percentage,
+                            "networkPolicy": network_policy
+                        }
+                    }
+                }
+            });
+            let output = run_to_json(cart_lines_discounts_generate_run, &input.to_string());
+
+            check_run_invariants(&input, &output)?;
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn handles_arbitrary_responses(json_body in arbitrary_json(OPERATION_KEYS)) {
+            let input = hybrid_input("MERGE").fetch_result(200, json_body).to_json();
+            let output = run_to_json(cart_lines_discounts_generate_run, &input.to_string());
+
+            prop_assert!(output["operations"].is_array(), "{output}");
+        }
+
+        #[test]
+        fn handles_corrupted_responses(
+            json_body in corrupted(
+                vec![
+                    json!([remote_product_discount()]),
+                    json!([{ "enteredDiscountCodesAccept": { "codes": [{ "code": "SUMMER10" }] } }]),
+                    json!([local_order_discount_json(), { "vetoLocalDiscounts": true }]),
+                ],
+                OPERATION_KEYS,
+            )
+        ) {
+            let input = hybrid_input("MERGE").fetch_result(200, json_body).to_json();
+            let output = run_to_json(cart_lines_discounts_generate_run, &input.to_string());
+
+            prop_assert!(output["operations"].is_array(), "{output}");
+        }
+    }
+
+    fn local_order_discount_json() -> serde_json::Value {
+        json!({
+            "orderDiscountsAdd": {
+                "selectionStrategy": "FIRST",
+                "candidates": [
+                    {
+                        "value": { "fixedAmount": { "amount": "5.0" } },
+                        "targets": [{ "orderSubtotal": { "excludedCartLineIds": [] } }]
+                    }
+                ]
+            }
+        })
+    }
 }
@@ -0,0 +1,143 @@
+// [START discount-function.discount-code-rejection]
+use discount_core::explain::explain;
+use shopify_function::prelude::*;
+use shopify_function::wasm_api::{read::Error, Value};
+use std::fmt;
+
+/// Discount codes rejected by the server, with the reason for each rejection.
+/// Shopify has no operation to reject a code, so the rejections are recorded in
+/// the explain trace, and its function logs, where support can look them up.
+#[derive(Deserialize)]
+#[shopify_function(rename_all = "camelCase")]
+pub struct EnteredDiscountCodesReject {
+    pub codes: Vec<RejectedDiscountCode>,
+}
+
+#[derive(Deserialize)]
+#[shopify_function(rename_all = "camelCase")]
+pub struct RejectedDiscountCode {
+    pub code: String,
+    pub reason: RejectionReason,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RejectionReason {
+    Expired,
+    MinimumNotMet,
+    CustomerNotEligible,
+    UsageLimitReached,
+    NotFound,
+    /// A reason this version of the function doesn't know about yet.
+    Other(String),
+}
+
+impl shopify_function::wasm_api::Deserialize for RejectionReason {
+    fn deserialize(value: &Value) -> Result<Self, Error> {
+        let reason: String = shopify_function::wasm_api::Deserialize::deserialize(value)?;
+        Ok(match reason.as_str() {
+            "EXPIRED" => Self::Expired,
+            "MINIMUM_NOT_MET" => Self::MinimumNotMet,
+            "CUSTOMER_NOT_ELIGIBLE" => Self::CustomerNotEligible,
+            "USAGE_LIMIT_REACHED" => Self::UsageLimitReached,
+            "NOT_FOUND" => Self::NotFound,
+            _ => Self::Other(reason),
+        })
+    }
+}
+
+impl fmt::Display for RejectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Expired => f.write_str("EXPIRED"),
+            Self::MinimumNotMet => f.write_str("MINIMUM_NOT_MET"),
+            Self::CustomerNotEligible => f.write_str("CUSTOMER_NOT_ELIGIBLE"),
+            Self::UsageLimitReached => f.write_str("USAGE_LIMIT_REACHED"),
+            Self::NotFound => f.write_str("NOT_FOUND"),
+            Self::Other(reason) => f.write_str(reason),
+        }
+    }
+}
+
+impl fmt::Display for RejectedDiscountCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Discount code {} rejected: {}", self.code, self.reason)?;
+        if let Some(message) = &self.message {
+            write!(f, " ({message})")?;
+        }
+        Ok(())
+    }
+}
+
+impl EnteredDiscountCodesReject {
+    /// Records one decision per rejected code in the explain trace.
+    pub fn log(&self) {
+        for rejected_code in &self.codes {
+            explain!("{rejected_code}");
+        }
+    }
+}
+// [END discount-function.discount-code-rejection]
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use discount_core::explain;
+
+    #[test]
+    fn formats_rejected_discount_codes_for_logs() {
+        let rejected_code = RejectedDiscountCode {
+            code: "SUMMER10".to_string(),
+            reason: RejectionReason::MinimumNotMet,
+            message: Some("Spend $50 more to use this code".to_string()),
+        };
+
+        assert_eq!(
+            rejected_code.to_string(),
+            "Discount code SUMMER10 rejected: MINIMUM_NOT_MET (Spend $50 more to use this code)"
+        );
+    }
+
+    #[test]
+    fn keeps_unknown_rejection_reasons() {
+        let rejected_code = RejectedDiscountCode {
+            code: "WINTER".to_string(),
+            reason: RejectionReason::Other("REGION_BLOCKED".to_string()),
+            message: None,
+        };
+
+        assert_eq!(
+            rejected_code.to_string(),
+            "Discount code WINTER rejected: REGION_BLOCKED"
+        );
+    }
+
+    #[test]
+    fn explains_each_rejected_code() {
+        let rejections = EnteredDiscountCodesReject {
+            codes: vec![
+                RejectedDiscountCode {
+                    code: "SUMMER10".to_string(),
+                    reason: RejectionReason::Expired,
+                    message: None,
+                },
+                RejectedDiscountCode {
+                    code: "VIP".to_string(),
+                    reason: RejectionReason::CustomerNotEligible,
+                    message: None,
+                },
+            ],
+        };
+        explain::take_trace();
+
+        rejections.log();
+
+        assert_eq!(
+            explain::take_trace(),
+            [
+                "Discount code SUMMER10 rejected: EXPIRED",
+                "Discount code VIP rejected: CUSTOMER_NOT_ELIGIBLE"
+            ]
+        );
+    }
+}
@@ -0,0 +1,60 @@
+// [START discount-function.fetch-policy]
+use discount_core::money::Money;
+use shopify_function::prelude::*;
+
+/// Configuration read by the fetch targets from the discount metafield.
+#[derive(Deserialize, Default)]
+#[shopify_function(rename_all = "camelCase")]
+pub struct FetchConfiguration {
+    #[shopify_function(default)]
+    pub fetch_policy: FetchPolicy,
+}
+
+/// Decides whether the fetch targets need to call the external server. Each
+/// condition is optional, and the request is only sent when all of the
+/// configured conditions are met.
+#[derive(Deserialize, Default)]
+#[shopify_function(rename_all = "camelCase")]
+pub struct FetchPolicy {
+    /// Only send the request when the buyer entered at least one discount code.
+    #[shopify_function(default)]
+    pub require_discount_codes: bool,
+    /// Only send the request when the cart subtotal reaches this amount.
+    #[shopify_function(default)]
+    pub minimum_subtotal: Option<Money>,
+    /// Only send the request for logged-in buyers.
+    #[shopify_function(default)]
+    pub require_authenticated_buyer: bool,
+}
+
+/// The facts about the fetch input that the policy depends on.
+pub struct FetchConditions {
+    pub has_discount_codes: bool,
+    /// `None` when the subtotal isn't a number, which reaches no minimum.
+    pub subtotal: Option<Money>,
+    pub is_authenticated_buyer: bool,
+}
+
+impl FetchPolicy {
+    pub fn should_fetch(&self, conditions: &FetchConditions) -> bool {
+        if self.require_discount_codes && !conditions.has_discount_codes {
+            return false;
+        }
+
+        if let Some(minimum_subtotal) = self.minimum_subtotal {
+            if conditions
+                .subtotal
+                .is_none_or(|subtotal| subtotal < minimum_subtotal)
+            {
+                return false;
+            }
+        }
+
+        if self.require_authenticated_buyer && !conditions.is_authenticated_buyer {
+            return false;
+        }
+
+        true
+    }
+}
+// [END discount-function.fetch-policy]
@@ -0,0 +1,76 @@
+// [START discount-function.fetch-response]
+use discount_core::explain::explain;
+use discount_core::{schema, ErrorKind};
+use shopify_function::wasm_api::{read::Error, Deserialize, Value};
+
+/// The JSON body of the server's response. The body comes from outside of
+/// Shopify, so a body that doesn't have the expected shape is kept as
+/// `Malformed` instead of failing the whole function, and the run targets
+/// handle it like a failed request.
+pub enum FetchResponse<T> {
+    Operations(Vec<T>),
+    /// Why the body doesn't have the expected shape.
+    Malformed(String),
+}
+
+impl<T: Deserialize> Deserialize for FetchResponse<T> {
+    fn deserialize(value: &Value) -> Result<Self, Error> {
+        match Vec::<T>::deserialize(value) {
+            Ok(operations) => Ok(Self::Operations(operations)),
+            Err(error) => Ok(Self::Malformed(error.to_string())),
+        }
+    }
+}
+
+/// The operations of a successful response with a well-formed body, or the
+/// error that makes the run targets ignore the response.
+pub fn response_operations<T>(
+    status: i32,
+    json_body: Option<&FetchResponse<T>>,
+) -> Result<&[T], ErrorKind> {
+    if !(200..300).contains(&status) {
+        return Err(ErrorKind::FetchFailed { status });
+    }
+    match json_body {
+        Some(FetchResponse::Operations(operations)) => Ok(operations),
+        Some(FetchResponse::Malformed(reason)) => Err(ErrorKind::MalformedFetchResponse {
+            reason: reason.clone(),
+        }),
+        None => Err(ErrorKind::MalformedFetchResponse {
+            reason: "no JSON body".to_string(),
+        }),
+    }
+}
+
+/// The selection strategy of a discount operation. A strategy that Shopify
+/// doesn't know is deserialized as `Other`, which can't be written to the
+/// output, so operations from the server with such a strategy are dropped.
+pub trait SelectionStrategy {
+    fn is_known(&self) -> bool;
+}
+
+macro_rules! impl_selection_strategy {
+    ($($strategy:ty),*) => {
+        $(impl SelectionStrategy for $strategy {
+            fn is_known(&self) -> bool {
+                !matches!(self, <$strategy>::Other)
+            }
+        })*
+    };
+}
+
+impl_selection_strategy!(
+    schema::ProductDiscountSelectionStrategy,
+    schema::OrderDiscountSelectionStrategy,
+    schema::DeliveryDiscountSelectionStrategy
+);
+
+/// Whether a discount operation from the server has a known selection strategy,
+/// logging the operations that are dropped.
+pub fn known_selection_strategy(strategy: &impl SelectionStrategy) -> bool {
+    if !strategy.is_known() {
+        explain!("ignoring server's discount operation with an unknown selection strategy");
+    }
+    strategy.is_known()
+}
+// [END discount-function.fetch-response]
@@ -1,23 +1,45 @@
-use std::process;
 pub mod cart_delivery_options_discounts_generate_run;
 pub mod cart_lines_discounts_generate_run;
+pub mod discount_code_rejection;
+pub mod fetch_policy;
+pub mod fetch_response;
+pub mod network_policy;
+pub mod request_fingerprint;
 // [START discount-function.main]
 pub mod cart_delivery_options_discounts_generate_fetch;
 pub mod cart_lines_discounts_generate_fetch;
+#[cfg(not(target_arch = "wasm32"))]
+use discount_function_devtools::{run_to_json, runner::Export};
 use shopify_function::typegen;
 
-#[typegen("schema.graphql")]
+// The output types come from discount-core, and the discount classes are read
+// as strings for `DiscountClasses`
+#[typegen(
+    "schema.graphql",
+    enums_as_str = ["CountryCode", "CurrencyCode", "DiscountClass", "LanguageCode"]
+)]
 pub mod schema {
-    #[query("src/cart_delivery_options_discounts_generate_fetch.graphql")]
+    #[query(
+        "src/cart_delivery_options_discounts_generate_fetch.graphql",
+        custom_scalar_overrides = {
+            "Input.discount.metafield.jsonValue" => super::fetch_policy::FetchConfiguration
+        }
+    )]
     pub mod cart_delivery_options_discounts_generate_fetch {}
 
-    #[query("src/cart_lines_discounts_generate_fetch.graphql")]
+    #[query(
+        "src/cart_lines_discounts_generate_fetch.graphql",
+        custom_scalar_overrides = {
+            "Input.discount.metafield.jsonValue" => super::fetch_policy::FetchConfiguration
+        }
+    )]
     pub mod cart_lines_discounts_generate_fetch {}
 
     #[query(
         "src/cart_delivery_options_discounts_generate_run.graphql",
         custom_scalar_overrides = {
-            "Input.fetchResult.jsonBody" => super::cart_delivery_options_discounts_generate_run::JsonBody
+            "Input.fetchResult.jsonBody" => super::cart_delivery_options_discounts_generate_run::JsonBody,
+            "Input.discount.metafield.jsonValue" => super::cart_delivery_options_discounts_generate_run::DiscountConfiguration
         }
     )]
     pub mod cart_delivery_options_discounts_generate_run {}
@@ -25,14 +47,153 @@ pub mod schema {
     #[query (
         "src/cart_lines_discounts_generate_run.graphql", 
         custom_scalar_overrides = {
-            "Input.fetchResult.jsonBody" => super::cart_lines_discounts_generate_run::JsonBody
+            "Input.fetchResult.jsonBody" => super::cart_lines_discounts_generate_run::JsonBody,
+            "Input.discount.metafield.jsonValue" => super::cart_lines_discounts_generate_run::DiscountConfiguration
         }
     )]
     pub mod cart_lines_discounts_generate_run {}
 }
 // [END discount-function.main]
 
+#[cfg(target_arch = "wasm32")]
 fn main() {
     eprintln!("Please invoke a named export.");
-    process::exit(1);
+    std::process::exit(1);
+}
+
+/// The exports run by the native build.
+#[cfg(not(target_arch = "wasm32"))]
+const EXPORTS: &[Export] = &[
+    ("cart_lines_discounts_generate_fetch", |input| {
+        run_to_json(
+            cart_lines_discounts_generate_fetch::cart_lines_discounts_generate_fetch,
+            input,
+        )
+    }),
+    ("cart_lines_discounts_generate_run", |input| {
+        run_to_json(
+            cart_lines_discounts_generate_run::cart_lines_discounts_generate_run,
+            input,
+        )
+    }),
+    ("cart_delivery_options_discounts_generate_fetch", |input| {
+        run_to_json(cart_delivery_options_discounts_generate_fetch::cart_delivery_options_discounts_generate_fetch, input)
+    }),
+    ("cart_delivery_options_discounts_generate_run", |input| {
+        run_to_json(cart_delivery_options_discounts_generate_run::cart_delivery_options_discounts_generate_run, input)
+    }),
+];
+
+// Natively, runs a named export with a JSON input from a file or stdin
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    discount_function_devtools::runner::main(EXPORTS, discount_core::explain::take_trace);
+}
+
+#[cfg(test)]
+mod tests {
+    use discount_function_devtools::budgets::{
+        check_instruction_budgets, check_wasm_size, large_cart,
+    };
+    use discount_function_devtools::differential::assert_matches_javascript;
+    use discount_function_devtools::replay::assert_replays;
+    use serde_json::json;
+
+    #[test]
+    #[ignore = "builds the release Wasm module"]
+    fn stays_within_wasm_size_budget() {
+        check_wasm_size(env!("CARGO_MANIFEST_DIR"));
+    }
+
+    #[test]
+    #[ignore = "builds the release Wasm module and needs function-runner"]
+    fn stays_within_instruction_budgets() {
+        let line_count = 250;
+        let configuration = json!({
+            "cartLinePercentage": 20,
+            "orderPercentage": 10,
+            "deliveryPercentage": 50,
+            "networkPolicy": "MERGE"
+        });
+        // The server discounts every line, so the run target handles a response
+        // as large as the cart
+        let cart_lines_response = json!([{
+            "enteredDiscountCodesAccept": {
+                "codes": [{ "code": "SAVE20" }]
+            },
+            "productDiscountsAdd": {
+                "selectionStrategy": "ALL",
+                "candidates": (0..line_count)
+                    .map(|index| json!({
+                        "value": { "percentage": { "value": "10" } },
+                        "targets": [{ "cartLine": { "id": format!("gid://shopify/CartLine/{index}") } }]
+                    }))
+                    .collect::<Vec<_>>()
+            }
+        }]);
+        let delivery_response = json!([{
+            "deliveryOptionDecisions": (0..3)
+                .map(|index| json!({
+                    "deliveryGroupId": format!("gid://shopify/CartDeliveryGroup/{index}"),
+                    "deliveryOptionHandle": "standard",
+                    "percentage": "100"
+                }))
+                .collect::<Vec<_>>()
+        }]);
+
+        check_instruction_budgets(
+            env!("CARGO_MANIFEST_DIR"),
+            &[
+                (
+                    "cart_lines_discounts_generate_fetch",
+                    large_cart(line_count)
+                        .entered_discount_codes(["SAVE20"])
+                        .build(),
+                ),
+                (
+                    "cart_lines_discounts_generate_run",
+                    large_cart(line_count)
+                        .discount_classes(["PRODUCT", "ORDER"])
+                        .metafield(configuration.clone())
+                        .fetch_result(200, cart_lines_response)
+                        .build(),
+                ),
+                (
+                    "cart_delivery_options_discounts_generate_fetch",
+                    large_cart(line_count)
+                        .entered_discount_codes(["FREESHIPPING"])
+                        .build(),
+                ),
+                (
+                    "cart_delivery_options_discounts_generate_run",
+                    large_cart(line_count)
+                        .discount_classes(["SHIPPING"])
+                        .metafield(configuration)
+                        .fetch_result(200, delivery_response)
+                        .build(),
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    #[ignore = "needs FUNCTION_LOGS_DIR"]
+    fn replays_function_logs() {
+        let dir = std::env::var("FUNCTION_LOGS_DIR")
+            .expect("Set FUNCTION_LOGS_DIR to a directory of function run logs");
+        assert_replays(dir, super::EXPORTS);
+    }
+
+    #[test]
+    #[ignore = "needs node"]
+    fn matches_javascript_implementation() {
+        assert_matches_javascript(
+            env!("CARGO_MANIFEST_DIR"),
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../../../../javascript/network-access/extensions/discount-function"
+            ),
+            super::EXPORTS,
+        );
+    }
 }
@@ -0,0 +1,70 @@
+// [START discount-function.network-policy]
+use discount_core::explain::explain;
+use shopify_function::wasm_api::{read::Error, Deserialize, Value};
+
+/// Controls how the operations returned by the external server are combined
+/// with the operations computed locally from the discount metafield.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum NetworkPolicy {
+    /// Apply the local operations, followed by the ones returned by the server.
+    #[default]
+    Merge,
+    /// Apply only the operations returned by the server whenever a response is
+    /// available, and fall back to the local operations otherwise.
+    Replace,
+    /// Apply only the local operations, unless the server vetoes them.
+    Veto,
+}
+
+impl Deserialize for NetworkPolicy {
+    fn deserialize(value: &Value) -> Result<Self, Error> {
+        let policy: String = Deserialize::deserialize(value)?;
+        match policy.as_str() {
+            "MERGE" => Ok(Self::Merge),
+            "REPLACE" => Ok(Self::Replace),
+            "VETO" => Ok(Self::Veto),
+            _ => Err(Error::InvalidType),
+        }
+    }
+}
+
+/// Operations extracted from a successful response of the external server.
+pub struct RemoteOperations<T> {
+    pub discount_code_operations: Vec<T>,
+    pub discount_operations: Vec<T>,
+    pub veto_local_discounts: bool,
+}
+
+impl NetworkPolicy {
+    /// Combines the local operations with the remote ones, if any. Discount code
+    /// operations returned by the server are always applied first.
+    pub fn apply<T>(self, local_operations: Vec<T>, remote: Option<RemoteOperations<T>>) -> Vec<T> {
+        let Some(remote) = remote else {
+            explain!("no usable response from the server, applying only local discounts");
+            return local_operations;
+        };
+
+        let mut operations = remote.discount_code_operations;
+        match self {
+            Self::Merge => {
+                explain!("MERGE network policy, applying local and server discounts");
+                operations.extend(local_operations);
+                operations.extend(remote.discount_operations);
+            }
+            Self::Replace => {
+                explain!("REPLACE network policy, applying only server discounts");
+                operations.extend(remote.discount_operations);
+            }
+            Self::Veto => {
+                if remote.veto_local_discounts {
+                    explain!("VETO network policy, the server vetoed the local discounts");
+                } else {
+                    explain!("VETO network policy, applying only local discounts");
+                    operations.extend(local_operations);
+                }
+            }
+        }
+        operations
+    }
+}
+// [END discount-function.network-policy]
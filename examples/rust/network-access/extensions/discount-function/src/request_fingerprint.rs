@@ -0,0 +1,185 @@
+// [START discount-function.request-fingerprint]
+use discount_core::explain::explain;
+use discount_core::money::Money;
+use discount_core::schema;
+
+/// Header carrying the deterministic fingerprint of the request.
+pub const FINGERPRINT_HEADER: &str = "x-request-fingerprint";
+/// Header carrying the correlation ID set by the storefront, if any.
+pub const CORRELATION_ID_HEADER: &str = "x-correlation-id";
+
+/// Computes a deterministic fingerprint of everything the fetch target sends to
+/// the server: cart lines, entered discount codes and delivery options. Shopify
+/// can call the fetch target several times during the same checkout, and the
+/// server can use the fingerprint to cache and deduplicate those calls. Entries
+/// are sorted first, so the fingerprint doesn't depend on their order, and
+/// amounts are written exactly, so the same amount always gives the same entry.
+#[derive(Default)]
+pub struct RequestFingerprint {
+    entries: Vec<String>,
+}
+
+impl RequestFingerprint {
+    pub fn cart_line(&mut self, id: &str, quantity: i32, amount: Money) -> &mut Self {
+        self.entries.push(format!("line:{id}:{quantity}:{amount}"));
+        self
+    }
+
+    pub fn discount_code(&mut self, code: &str) -> &mut Self {
+        self.entries.push(format!("code:{code}"));
+        self
+    }
+
+    pub fn delivery_option(
+        &mut self,
+        delivery_group_id: &str,
+        handle: &str,
+        amount: Money,
+    ) -> &mut Self {
+        self.entries
+            .push(format!("delivery:{delivery_group_id}:{handle}:{amount}"));
+        self
+    }
+
+    pub fn finish(&mut self) -> String {
+        self.entries.sort();
+        let hash = self.entries.iter().fold(FNV_OFFSET_BASIS, |hash, entry| {
+            fnv1a(fnv1a(hash, entry.as_bytes()), b"\n")
+        });
+        format!("{hash:016x}")
+    }
+}
+
+/// The headers of a fetch request: JSON content types, the fingerprint of the
+/// request and, when the storefront set one, its correlation ID.
+pub fn request_headers(
+    fingerprint: &mut RequestFingerprint,
+    correlation_id: Option<String>,
+) -> Vec<schema::HttpRequestHeader> {
+    let fingerprint = fingerprint.finish();
+
+    // Explained so that function runs can be matched with the server logs
+    explain!(
+        "{FINGERPRINT_HEADER}: {fingerprint}, {CORRELATION_ID_HEADER}: {}",
+        correlation_id.as_deref().unwrap_or("none")
+    );
+
+    let mut headers = vec![
+        header("accept", "application/json"),
+        header("Content-Type", "application/json"),
+        header(FINGERPRINT_HEADER, fingerprint),
+    ];
+    if let Some(correlation_id) = correlation_id {
+        headers.push(header(CORRELATION_ID_HEADER, correlation_id));
+    }
+    headers
+}
+
+fn header(name: &str, value: impl Into<String>) -> schema::HttpRequestHeader {
+    schema::HttpRequestHeader {
+        name: name.to_string(),
+        value: value.into(),
+    }
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// 64-bit FNV-1a, which is small and stable across builds and platforms.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+// [END discount-function.request-fingerprint]
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(amount: f64) -> Money {
+        Money::from_f64(amount).unwrap()
+    }
+
+    #[test]
+    fn fingerprint_does_not_depend_on_order() {
+        let fingerprint = RequestFingerprint::default()
+            .cart_line("gid://shopify/CartLine/0", 1, money(10.0))
+            .cart_line("gid://shopify/CartLine/1", 2, money(40.0))
+            .discount_code("SAVE20")
+            .discount_code("FREESHIPPING")
+            .finish();
+        let reversed_fingerprint = RequestFingerprint::default()
+            .discount_code("FREESHIPPING")
+            .discount_code("SAVE20")
+            .cart_line("gid://shopify/CartLine/1", 2, money(40.0))
+            .cart_line("gid://shopify/CartLine/0", 1, money(10.0))
+            .finish();
+
+        assert_eq!(fingerprint, reversed_fingerprint);
+    }
+
+    #[test]
+    fn fingerprint_changes_with_request_contents() {
+        let fingerprint = |quantity, code: Option<&str>, amount| {
+            let mut fingerprint = RequestFingerprint::default();
+            fingerprint
+                .cart_line("gid://shopify/CartLine/0", quantity, money(10.0))
+                .delivery_option("gid://shopify/DeliveryGroup/0", "standard", money(amount));
+            if let Some(code) = code {
+                fingerprint.discount_code(code);
+            }
+            fingerprint.finish()
+        };
+
+        assert_ne!(fingerprint(1, None, 5.0), fingerprint(2, None, 5.0));
+        assert_ne!(
+            fingerprint(1, None, 5.0),
+            fingerprint(1, Some("SAVE20"), 5.0)
+        );
+        assert_ne!(fingerprint(1, None, 5.0), fingerprint(1, None, 7.5));
+    }
+
+    #[test]
+    fn fingerprint_writes_amounts_exactly() {
+        let fingerprint = |amount| {
+            RequestFingerprint::default()
+                .cart_line("gid://shopify/CartLine/0", 1, amount)
+                .finish()
+        };
+
+        // 0.1 + 0.2 is written 0.30000000000000004 as an `f64`
+        assert_eq!(fingerprint(money(0.1 + 0.2)), fingerprint(money(0.3)));
+    }
+
+    #[test]
+    fn builds_headers_with_the_fingerprint() {
+        let headers = request_headers(
+            RequestFingerprint::default().discount_code("SAVE20"),
+            Some("checkout-123".to_string()),
+        );
+
+        let names: Vec<&str> = headers.iter().map(|header| header.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "accept",
+                "Content-Type",
+                FINGERPRINT_HEADER,
+                CORRELATION_ID_HEADER
+            ]
+        );
+        assert_eq!(
+            headers[2].value,
+            RequestFingerprint::default()
+                .discount_code("SAVE20")
+                .finish()
+        );
+        assert_eq!(headers[3].value, "checkout-123");
+    }
+
+    #[test]
+    fn empty_fingerprint_is_the_offset_basis() {
+        assert_eq!(RequestFingerprint::default().finish(), "cbf29ce484222325");
+    }
+}
@@ -0,0 +1,18 @@
+#!/usr/bin/env bash
+# Runs the ignored tests of the Rust examples: the Wasm size and instruction
+# budgets, and the differential tests against the JavaScript examples. They
+# need the wasm32-wasip1 target, Node, and function-runner on the PATH, or its
+# path in FUNCTION_RUNNER. The function log replays only run when
+# FUNCTION_LOGS_DIR is set.
+set -euo pipefail
+
+cd "$(dirname "$0")"
+skip=()
+if [ -z "${FUNCTION_LOGS_DIR:-}" ]; then
+  skip=(--skip replays_function_logs)
+fi
+
+for example in default metafield network-access; do
+  echo "== $example"
+  (cd "$example/extensions/discount-function" && cargo test -- --ignored "${skip[@]}")
+done